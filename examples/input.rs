@@ -0,0 +1,27 @@
+use gpu_md::register_default_keybindings;
+use gpu_md::text_input::TextInput;
+use gpui::*;
+
+/// The smallest embedding of the editor: a single `TextInput` filling the
+/// window. `src/main.rs` adds the find bar, the preview and file handling.
+fn main() {
+    App::new().run(|cx: &mut AppContext| {
+        register_default_keybindings(cx);
+        let bounds = Bounds::centered(None, size(px(600.0), px(400.0)), cx);
+        let window = cx
+            .open_window(
+                WindowOptions {
+                    window_bounds: Some(WindowBounds::Windowed(bounds)),
+                    ..Default::default()
+                },
+                |cx| cx.new_view(|cx| TextInput::new(cx).with_text("# Hello\n\nStart typing…")),
+            )
+            .unwrap();
+        window
+            .update(cx, |text_input, cx| {
+                cx.focus(&text_input.focus_handle);
+                cx.activate(true);
+            })
+            .unwrap();
+    });
+}
@@ -0,0 +1,393 @@
+use ropey::Rope;
+use std::convert::Infallible;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// A location in the document as a line index and a byte column within that
+/// line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TextPosition {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl TextPosition {
+    pub fn new(line: usize, column: usize) -> Self {
+        Self { line, column }
+    }
+}
+
+/// The newline convention of a file. The buffer itself always uses `\n`; the
+/// original convention is restored when the file is written back.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineEnding {
+    #[default]
+    Unix,
+    Windows,
+}
+
+impl LineEnding {
+    /// Picks the convention of the first line break in `text`.
+    pub fn detect(text: &str) -> Self {
+        match text.find('\n') {
+            Some(ix) if text[..ix].ends_with('\r') => LineEnding::Windows,
+            _ => LineEnding::Unix,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Unix => "\n",
+            LineEnding::Windows => "\r\n",
+        }
+    }
+
+    pub fn normalize(text: &str) -> String {
+        text.replace("\r\n", "\n")
+    }
+}
+
+/// The document text, stored in a rope so that edits and line lookups stay
+/// O(log n) regardless of file size. All offsets are UTF-8 byte offsets.
+#[derive(Clone, Default)]
+pub struct Buffer {
+    rope: Rope,
+    version: usize,
+}
+
+impl Buffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Incremented on every edit, so callers can tell whether the text changed
+    /// since they last looked.
+    pub fn version(&self) -> usize {
+        self.version
+    }
+
+    /// Whether both buffers hold the same text, whatever their versions.
+    pub fn same_text(&self, other: &Buffer) -> bool {
+        self.rope == other.rope
+    }
+
+    pub fn len(&self) -> usize {
+        self.rope.len_bytes()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rope.len_bytes() == 0
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.rope.len_lines()
+    }
+
+    /// Byte offset of the first character of `line`.
+    pub fn line_start(&self, line: usize) -> usize {
+        self.rope.line_to_byte(line)
+    }
+
+    /// Byte offset of the end of `line`, excluding its newline.
+    pub fn line_end(&self, line: usize) -> usize {
+        if line + 1 < self.line_count() {
+            self.rope.line_to_byte(line + 1) - 1
+        } else {
+            self.len()
+        }
+    }
+
+    pub fn line_len(&self, line: usize) -> usize {
+        self.line_end(line) - self.line_start(line)
+    }
+
+    /// Text of `line` without its trailing newline.
+    pub fn line(&self, line: usize) -> String {
+        self.text_for_range(self.line_start(line)..self.line_end(line))
+    }
+
+    pub fn line_for_offset(&self, offset: usize) -> usize {
+        self.rope.byte_to_line(offset)
+    }
+
+    /// Position just past the last character of the document.
+    pub fn max_position(&self) -> TextPosition {
+        let line = self.line_count() - 1;
+        TextPosition::new(line, self.line_len(line))
+    }
+
+    pub fn offset_for_position(&self, position: TextPosition) -> usize {
+        self.line_start(position.line) + position.column
+    }
+
+    pub fn position_for_offset(&self, offset: usize) -> TextPosition {
+        let line = self.line_for_offset(offset);
+        TextPosition::new(line, offset - self.line_start(line))
+    }
+
+    /// Clamps `position` into the document and moves it back onto a
+    /// character boundary.
+    pub fn clip_position(&self, position: TextPosition) -> TextPosition {
+        let line = position.line.min(self.line_count() - 1);
+        let offset = self.line_start(line) + position.column.min(self.line_len(line));
+        let offset = self.rope.char_to_byte(self.rope.byte_to_char(offset));
+        TextPosition::new(line, offset - self.line_start(line))
+    }
+
+    pub fn offset_to_utf16(&self, offset: usize) -> usize {
+        let offset = offset.min(self.len());
+        self.rope.char_to_utf16_cu(self.rope.byte_to_char(offset))
+    }
+
+    pub fn offset_from_utf16(&self, offset_utf16: usize) -> usize {
+        let offset_utf16 = offset_utf16.min(self.rope.len_utf16_cu());
+        self.rope
+            .char_to_byte(self.rope.utf16_cu_to_char(offset_utf16))
+    }
+
+    pub fn text_for_range(&self, range: Range<usize>) -> String {
+        self.rope.byte_slice(range).to_string()
+    }
+
+    pub fn replace(&mut self, range: Range<usize>, text: &str) {
+        let start = self.rope.byte_to_char(range.start);
+        let end = self.rope.byte_to_char(range.end);
+        self.rope.remove(start..end);
+        self.rope.insert(start, text);
+        self.version += 1;
+    }
+
+    /// The grapheme boundary before `position`, wrapping to the end of the
+    /// previous line at the start of a line.
+    pub fn previous_boundary(&self, position: TextPosition) -> TextPosition {
+        if position.column == 0 {
+            return match position.line.checked_sub(1) {
+                Some(line) => TextPosition::new(line, self.line_len(line)),
+                None => position,
+            };
+        }
+
+        let column = self
+            .line(position.line)
+            .grapheme_indices(true)
+            .rev()
+            .find_map(|(idx, _)| (idx < position.column).then_some(idx))
+            .unwrap_or(0);
+        TextPosition::new(position.line, column)
+    }
+
+    /// The grapheme boundary after `position`, wrapping to the start of the
+    /// next line at the end of a line.
+    pub fn next_boundary(&self, position: TextPosition) -> TextPosition {
+        let line_len = self.line_len(position.line);
+        if position.column >= line_len {
+            return if position.line + 1 < self.line_count() {
+                TextPosition::new(position.line + 1, 0)
+            } else {
+                position
+            };
+        }
+
+        let column = self
+            .line(position.line)
+            .grapheme_indices(true)
+            .find_map(|(idx, _)| (idx > position.column).then_some(idx))
+            .unwrap_or(line_len);
+        TextPosition::new(position.line, column)
+    }
+
+    /// The start of the word before `position`, wrapping to the end of the
+    /// previous line at the start of a line. Words are unicode word bounds,
+    /// so a run of punctuation or an emoji counts as a word of its own and
+    /// whitespace is skipped over.
+    pub fn previous_word_boundary(&self, position: TextPosition) -> TextPosition {
+        if position.column == 0 {
+            return self.previous_boundary(position);
+        }
+
+        let column = self
+            .line(position.line)
+            .split_word_bound_indices()
+            .rev()
+            .find_map(|(idx, word)| (idx < position.column && !is_whitespace(word)).then_some(idx))
+            .unwrap_or(0);
+        TextPosition::new(position.line, column)
+    }
+
+    /// The end of the word after `position`, wrapping to the start of the
+    /// next line at the end of a line.
+    pub fn next_word_boundary(&self, position: TextPosition) -> TextPosition {
+        let line_len = self.line_len(position.line);
+        if position.column >= line_len {
+            return self.next_boundary(position);
+        }
+
+        let column = self
+            .line(position.line)
+            .split_word_bound_indices()
+            .find_map(|(idx, word)| {
+                let end = idx + word.len();
+                (end > position.column && !is_whitespace(word)).then_some(end)
+            })
+            .unwrap_or(line_len);
+        TextPosition::new(position.line, column)
+    }
+
+    /// The unicode word containing `position`, or the one just before it at
+    /// the end of a line.
+    pub fn word_at(&self, position: TextPosition) -> Range<TextPosition> {
+        let text = self.line(position.line);
+        let columns = text
+            .split_word_bound_indices()
+            .map(|(idx, word)| idx..idx + word.len())
+            .find(|columns| columns.contains(&position.column) || columns.end == text.len())
+            .unwrap_or(position.column..position.column);
+        TextPosition::new(position.line, columns.start)
+            ..TextPosition::new(position.line, columns.end)
+    }
+}
+
+fn is_whitespace(word: &str) -> bool {
+    word.chars().all(char::is_whitespace)
+}
+
+impl From<&str> for Buffer {
+    fn from(text: &str) -> Self {
+        Self {
+            rope: Rope::from_str(text),
+            version: 0,
+        }
+    }
+}
+
+impl fmt::Display for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.rope, f)
+    }
+}
+
+/// The contents of a file: the text, always with `\n` line breaks, and the
+/// line ending convention that `Display` restores when it is written back.
+#[derive(Clone, Default)]
+pub struct Document {
+    pub buffer: Buffer,
+    pub line_ending: LineEnding,
+}
+
+impl Document {
+    pub fn new(text: &str) -> Self {
+        Self {
+            buffer: Buffer::from(LineEnding::normalize(text).as_str()),
+            line_ending: LineEnding::detect(text),
+        }
+    }
+}
+
+impl FromStr for Document {
+    type Err = Infallible;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Ok(Self::new(text))
+    }
+}
+
+impl fmt::Display for Document {
+    /// Writes the text using the line endings it was loaded with.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chunk in self.buffer.rope.chunks() {
+            match self.line_ending {
+                LineEnding::Unix => f.write_str(chunk)?,
+                line_ending => f.write_str(&chunk.replace('\n', line_ending.as_str()))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_text_ignores_version() {
+        let saved = Buffer::from("one\ntwo");
+        let mut buffer = saved.clone();
+        buffer.replace(0..3, "uno");
+        assert!(!buffer.same_text(&saved));
+        buffer.replace(0..3, "one");
+        assert_ne!(buffer.version(), saved.version());
+        assert!(buffer.same_text(&saved));
+    }
+
+    #[test]
+    fn document_keeps_windows_line_endings() {
+        let document: Document = "one\r\ntwo\r\n".parse().unwrap();
+        assert_eq!(document.line_ending, LineEnding::Windows);
+        assert_eq!(document.buffer.to_string(), "one\ntwo\n");
+        assert_eq!(document.buffer.line_count(), 3);
+        assert_eq!(document.to_string(), "one\r\ntwo\r\n");
+    }
+
+    #[test]
+    fn document_keeps_unix_line_endings() {
+        for text in ["one\ntwo", ""] {
+            let document = Document::new(text);
+            assert_eq!(document.line_ending, LineEnding::Unix);
+            assert_eq!(document.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn document_writes_edits_with_its_line_ending() {
+        let mut document = Document::new("a\r\nb");
+        document.buffer.replace(1..1, "\nc");
+        assert_eq!(document.to_string(), "a\r\nc\r\nb");
+    }
+
+    fn at(line: usize, column: usize) -> TextPosition {
+        TextPosition::new(line, column)
+    }
+
+    #[test]
+    fn word_boundaries_stop_at_punctuation() {
+        let buffer = Buffer::from("foo,bar(baz)  qux");
+        assert_eq!(buffer.next_word_boundary(at(0, 0)), at(0, 3));
+        assert_eq!(buffer.next_word_boundary(at(0, 3)), at(0, 4));
+        assert_eq!(buffer.next_word_boundary(at(0, 12)), at(0, 17));
+        assert_eq!(buffer.previous_word_boundary(at(0, 14)), at(0, 11));
+        assert_eq!(buffer.previous_word_boundary(at(0, 8)), at(0, 7));
+        assert_eq!(buffer.word_at(at(0, 5)), at(0, 4)..at(0, 7));
+
+        // A period between letters doesn't end the word.
+        let buffer = Buffer::from("foo.bar baz");
+        assert_eq!(buffer.next_word_boundary(at(0, 0)), at(0, 7));
+    }
+
+    #[test]
+    fn word_boundaries_in_cjk_and_emoji() {
+        // Each ideograph is a word, and an emoji with its modifier is one.
+        let buffer = Buffer::from("漢字 👍🏽ok");
+        assert_eq!(buffer.next_word_boundary(at(0, 0)), at(0, 3));
+        assert_eq!(buffer.next_word_boundary(at(0, 3)), at(0, 6));
+        assert_eq!(buffer.next_word_boundary(at(0, 6)), at(0, 15));
+        assert_eq!(buffer.previous_word_boundary(at(0, 17)), at(0, 15));
+        assert_eq!(buffer.previous_word_boundary(at(0, 15)), at(0, 7));
+        assert_eq!(buffer.word_at(at(0, 9)), at(0, 7)..at(0, 15));
+        assert_eq!(buffer.previous_boundary(at(0, 15)), at(0, 7));
+    }
+
+    #[test]
+    fn word_boundaries_cross_lines() {
+        let buffer = Buffer::from("one\n  two\n");
+        assert_eq!(buffer.next_word_boundary(at(0, 3)), at(1, 0));
+        assert_eq!(buffer.next_word_boundary(at(1, 0)), at(1, 5));
+        assert_eq!(buffer.previous_word_boundary(at(1, 0)), at(0, 3));
+        assert_eq!(buffer.previous_word_boundary(at(1, 2)), at(1, 0));
+        assert_eq!(buffer.next_word_boundary(at(2, 0)), at(2, 0));
+        assert_eq!(buffer.previous_word_boundary(at(0, 0)), at(0, 0));
+        // At the end of a line, the word before it.
+        assert_eq!(buffer.word_at(at(0, 3)), at(0, 0)..at(0, 3));
+    }
+}
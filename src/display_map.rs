@@ -0,0 +1,373 @@
+use crate::buffer::TextPosition;
+use std::ops::Range;
+
+/// A visual row: the part of a buffer line that fits on one line of the
+/// viewport once soft wrapping is applied.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DisplayRow {
+    pub line: usize,
+    /// Byte columns of `line` shown on this row.
+    pub columns: Range<usize>,
+    /// Whether this is the last row of `line`.
+    pub is_line_end: bool,
+}
+
+impl DisplayRow {
+    /// Whether `position` is shown on this row. A position on a wrap
+    /// boundary belongs to the row it starts.
+    pub fn contains(&self, position: TextPosition) -> bool {
+        position.line == self.line
+            && self.columns.start <= position.column
+            && (position.column < self.columns.end || self.is_line_end)
+    }
+}
+
+/// Maps buffer lines to the display rows they occupy when soft wrapped. The
+/// buffer is never changed. Edited lines are laid out on the next `sync`, but
+/// only the lines on screen are wrapped there; the rest keep their previous
+/// rows, or a single unwrapped row, until `wrap_stale` gets to them.
+#[derive(Default)]
+pub struct DisplayMap {
+    /// The rows of each line, `None` for lines edited since the last `sync`.
+    line_rows: Vec<Option<LineRows>>,
+    /// Index of the first row of each line, followed by the total row count.
+    row_starts: Vec<usize>,
+}
+
+#[derive(Clone)]
+struct LineRows {
+    /// Byte columns of the line shown on each row.
+    columns: Vec<Range<usize>>,
+    /// Whether the line has to be wrapped again, because the wrap width
+    /// changed or it was never wrapped.
+    stale: bool,
+}
+
+impl DisplayMap {
+    pub fn new(line_count: usize) -> Self {
+        Self {
+            line_rows: vec![None; line_count],
+            row_starts: Vec::new(),
+        }
+    }
+
+    /// Marks every line for rewrapping, e.g. after the wrap width changed.
+    /// Lines keep their current rows until they are wrapped again.
+    pub fn invalidate_all(&mut self) {
+        for rows in self.line_rows.iter_mut().flatten() {
+            rows.stale = true;
+        }
+    }
+
+    /// Records that `old_lines` of the buffer were replaced by
+    /// `new_line_count` lines, which will be laid out on the next `sync`.
+    pub fn edit(&mut self, old_lines: Range<usize>, new_line_count: usize) {
+        invalidate_lines(&mut self.line_rows, old_lines, new_line_count);
+    }
+
+    /// Lays out the lines edited since the last sync and wraps the stale
+    /// lines within `visible`. Edited lines outside `visible` get a single
+    /// row and are left for `wrap_stale`. `wrap` returns the byte columns at
+    /// which the given line text breaks onto a new row.
+    pub fn sync(
+        &mut self,
+        visible: Range<usize>,
+        line_text: impl Fn(usize) -> String,
+        mut wrap: impl FnMut(&str) -> Vec<usize>,
+    ) {
+        let mut changed = self.row_starts.len() != self.line_rows.len() + 1;
+        for (line, rows) in self.line_rows.iter_mut().enumerate() {
+            let is_visible = visible.contains(&line);
+            match rows {
+                Some(rows) if !rows.stale || !is_visible => continue,
+                _ if is_visible => *rows = Some(wrap_line(&line_text(line), &mut wrap)),
+                _ => {
+                    let unwrapped = wrap_line(&line_text(line), &mut |_: &str| Vec::new());
+                    *rows = Some(LineRows {
+                        stale: true,
+                        ..unwrapped
+                    })
+                }
+            }
+            changed = true;
+        }
+        if changed {
+            self.update_row_starts();
+        }
+    }
+
+    /// Wraps up to `max_lines` of the lines left stale by `sync`, and
+    /// returns whether any stale lines remain.
+    pub fn wrap_stale(
+        &mut self,
+        max_lines: usize,
+        line_text: impl Fn(usize) -> String,
+        mut wrap: impl FnMut(&str) -> Vec<usize>,
+    ) -> bool {
+        let mut stale_lines = self
+            .line_rows
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, rows)| rows.as_ref().is_some_and(|rows| rows.stale));
+        let mut changed = false;
+        for (line, rows) in stale_lines.by_ref().take(max_lines) {
+            *rows = Some(wrap_line(&line_text(line), &mut wrap));
+            changed = true;
+        }
+        let has_more = stale_lines.next().is_some();
+        if changed {
+            self.update_row_starts();
+        }
+        has_more
+    }
+
+    /// Whether some lines still wait for `wrap_stale`.
+    pub fn has_stale_lines(&self) -> bool {
+        self.line_rows
+            .iter()
+            .any(|rows| rows.as_ref().is_some_and(|rows| rows.stale))
+    }
+
+    fn update_row_starts(&mut self) {
+        self.row_starts.clear();
+        let mut row = 0;
+        for rows in &self.line_rows {
+            self.row_starts.push(row);
+            row += rows.as_ref().map_or(1, |rows| rows.columns.len());
+        }
+        self.row_starts.push(row);
+    }
+
+    /// The line shown on `row` as of the last `sync`, for finding the lines
+    /// on screen before the map is synced again.
+    pub fn line_for_row(&self, row: usize) -> usize {
+        let line = self
+            .row_starts
+            .partition_point(|start| *start <= row)
+            .saturating_sub(1);
+        line.min(self.line_rows.len().saturating_sub(1))
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.row_starts.last().copied().unwrap_or(0)
+    }
+
+    pub fn row(&self, row: usize) -> DisplayRow {
+        let line = self.row_starts.partition_point(|start| *start <= row) - 1;
+        let rows = self.rows(line);
+        let ix = row - self.row_starts[line];
+        DisplayRow {
+            line,
+            columns: rows[ix].clone(),
+            is_line_end: ix + 1 == rows.len(),
+        }
+    }
+
+    /// Display rows occupied by `line`.
+    pub fn rows_for_line(&self, line: usize) -> Range<usize> {
+        self.row_starts[line]..self.row_starts[line + 1]
+    }
+
+    /// The row showing `position`. A position on a wrap boundary belongs to
+    /// the row it starts.
+    pub fn row_for_position(&self, position: TextPosition) -> usize {
+        let rows = self.rows(position.line);
+        let ix = rows
+            .iter()
+            .rposition(|columns| columns.start <= position.column)
+            .unwrap_or(0);
+        self.row_starts[position.line] + ix
+    }
+
+    fn rows(&self, line: usize) -> &[Range<usize>] {
+        &self.line_rows[line]
+            .as_ref()
+            .expect("display map used before sync")
+            .columns
+    }
+}
+
+/// Replaces the per-line entries of `old_lines` with `new_line_count` empty
+/// ones, for line caches to recompute after an edit.
+pub(crate) fn invalidate_lines<T>(
+    lines: &mut Vec<Option<T>>,
+    old_lines: Range<usize>,
+    new_line_count: usize,
+) {
+    lines.splice(
+        old_lines,
+        std::iter::repeat_with(|| None).take(new_line_count),
+    );
+}
+
+/// Splits `text` into rows at the columns returned by `wrap`.
+fn wrap_line(text: &str, wrap: &mut impl FnMut(&str) -> Vec<usize>) -> LineRows {
+    let mut start = 0;
+    let mut columns = Vec::new();
+    for boundary in wrap(text) {
+        if boundary > start && boundary < text.len() {
+            columns.push(start..boundary);
+            start = boundary;
+        }
+    }
+    columns.push(start..text.len());
+    LineRows {
+        columns,
+        stale: false,
+    }
+}
+
+/// Where tabs were expanded in a piece of text. gpui shapes a tab as a
+/// single glyph, so tabs are replaced by spaces up to the next tab stop
+/// before shaping, and columns of the text have to be converted to and from
+/// indices into the expanded text.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TabMap {
+    /// Column of each tab and the number of spaces it was expanded to.
+    tabs: Vec<(usize, usize)>,
+}
+
+impl TabMap {
+    /// Expands the tabs in `text` to tab stops every `tab_width` characters.
+    pub fn expand(text: &str, tab_width: usize) -> (String, Self) {
+        let tab_width = tab_width.max(1);
+        let mut expanded = String::with_capacity(text.len());
+        let mut tabs = Vec::new();
+        let mut width = 0;
+        for (column, ch) in text.char_indices() {
+            if ch == '\t' {
+                let spaces = tab_width - width % tab_width;
+                expanded.extend(std::iter::repeat_n(' ', spaces));
+                tabs.push((column, spaces));
+                width += spaces;
+            } else {
+                expanded.push(ch);
+                width += 1;
+            }
+        }
+        (expanded, Self { tabs })
+    }
+
+    /// The index in the expanded text of `column` of the original text.
+    pub fn to_expanded(&self, column: usize) -> usize {
+        self.tabs
+            .iter()
+            .take_while(|(tab, _)| *tab < column)
+            .map(|(_, spaces)| spaces - 1)
+            .sum::<usize>()
+            + column
+    }
+
+    /// The column of the original text at index `ix` of the expanded text.
+    /// Indices inside an expanded tab snap to the nearer side of the tab.
+    pub fn to_column(&self, ix: usize) -> usize {
+        let mut extra = 0;
+        for &(tab, spaces) in &self.tabs {
+            let start = tab + extra;
+            if ix <= start {
+                break;
+            }
+            if ix < start + spaces {
+                return if ix - start <= spaces / 2 {
+                    tab
+                } else {
+                    tab + 1
+                };
+            }
+            extra += spaces - 1;
+        }
+        ix - extra
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINES: [&str; 3] = ["abcdefghij", "abc", "abcdefgh"];
+
+    fn line_text(line: usize) -> String {
+        LINES[line].to_string()
+    }
+
+    /// Breaks lines every four bytes.
+    fn wrap_four(text: &str) -> Vec<usize> {
+        (4..text.len()).step_by(4).collect()
+    }
+
+    fn columns(map: &DisplayMap) -> Vec<Range<usize>> {
+        (0..map.row_count())
+            .map(|row| map.row(row).columns)
+            .collect()
+    }
+
+    #[test]
+    fn sync_wraps_only_visible_lines() {
+        let mut map = DisplayMap::new(3);
+        map.sync(0..1, line_text, wrap_four);
+        assert_eq!(columns(&map), [0..4, 4..8, 8..10, 0..3, 0..8]);
+        assert_eq!(map.rows_for_line(2), 4..5);
+        assert!(map.has_stale_lines());
+    }
+
+    #[test]
+    fn rows_contain_the_positions_they_start() {
+        let mut map = DisplayMap::new(3);
+        map.sync(0..3, line_text, wrap_four);
+        let position = TextPosition::new(0, 4);
+        assert!(!map.row(0).contains(position));
+        assert!(map.row(1).contains(position));
+        assert!(map.row(2).contains(TextPosition::new(0, 10)));
+        assert!(!map.row(3).contains(TextPosition::new(0, 10)));
+    }
+
+    #[test]
+    fn wrap_stale_wraps_in_chunks() {
+        let mut map = DisplayMap::new(3);
+        map.sync(0..0, line_text, wrap_four);
+        assert!(map.wrap_stale(2, line_text, wrap_four));
+        assert_eq!(columns(&map), [0..4, 4..8, 8..10, 0..3, 0..8]);
+        assert!(!map.wrap_stale(2, line_text, wrap_four));
+        assert_eq!(columns(&map), [0..4, 4..8, 8..10, 0..3, 0..4, 4..8]);
+        assert!(!map.has_stale_lines());
+    }
+
+    #[test]
+    fn invalidated_lines_keep_their_rows_until_rewrapped() {
+        let mut map = DisplayMap::new(3);
+        map.sync(0..3, line_text, wrap_four);
+        map.invalidate_all();
+        map.sync(2..3, line_text, |_| Vec::new());
+        assert_eq!(columns(&map), [0..4, 4..8, 8..10, 0..3, 0..8]);
+        assert!(!map.wrap_stale(10, line_text, |_| Vec::new()));
+        assert_eq!(columns(&map), [0..10, 0..3, 0..8]);
+    }
+
+    #[test]
+    fn line_for_row_is_clamped_after_edits() {
+        let mut map = DisplayMap::new(3);
+        map.sync(0..3, line_text, wrap_four);
+        assert_eq!(map.line_for_row(2), 0);
+        assert_eq!(map.line_for_row(5), 2);
+        map.edit(1..3, 0);
+        assert_eq!(map.line_for_row(5), 0);
+    }
+
+    #[test]
+    fn tabs_expand_to_the_next_tab_stop() {
+        let (expanded, tabs) = TabMap::expand("a\tb\tc", 4);
+        assert_eq!(expanded, "a   b   c");
+        let columns = (0..=5).map(|column| tabs.to_expanded(column));
+        assert_eq!(columns.collect::<Vec<_>>(), [0, 1, 4, 5, 8, 9]);
+
+        let (expanded, _) = TabMap::expand("\tabcd\t", 4);
+        assert_eq!(expanded, "    abcd    ");
+    }
+
+    #[test]
+    fn expanded_indices_snap_to_the_nearer_side_of_a_tab() {
+        let (_, tabs) = TabMap::expand("a\tb\tc", 4);
+        let columns = (0..=9).map(|ix| tabs.to_column(ix));
+        assert_eq!(columns.collect::<Vec<_>>(), [0, 1, 1, 2, 2, 3, 3, 4, 4, 5]);
+    }
+}
@@ -0,0 +1,329 @@
+use crate::buffer::TextPosition;
+use crate::display_map::TabMap;
+use gpui::Pixels;
+use std::ops::Range;
+
+/// A document-level selection. The `anchor` stays put while the `head` follows
+/// the cursor, so the head may come before the anchor.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Selection {
+    pub anchor: TextPosition,
+    pub head: TextPosition,
+}
+
+impl Selection {
+    pub fn new(anchor: TextPosition, head: TextPosition) -> Self {
+        Self { anchor, head }
+    }
+
+    pub fn cursor(position: TextPosition) -> Self {
+        Self::new(position, position)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.head
+    }
+
+    pub fn reversed(&self) -> bool {
+        self.head < self.anchor
+    }
+
+    pub fn start(&self) -> TextPosition {
+        self.anchor.min(self.head)
+    }
+
+    pub fn end(&self) -> TextPosition {
+        self.anchor.max(self.head)
+    }
+}
+
+/// Every selection in the editor, sorted by position and never overlapping.
+/// There is always at least one; the newest is the one the view scrolls to,
+/// the IME composes at and a mouse drag extends.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SelectionSet {
+    selections: Vec<Selection>,
+    newest: usize,
+}
+
+impl SelectionSet {
+    /// Sorts `selections` and merges the ones that overlap, or where a cursor
+    /// touches another selection. `newest` indexes into `selections`.
+    pub fn new(mut selections: Vec<Selection>, newest: usize) -> Self {
+        assert!(!selections.is_empty(), "a selection set can't be empty");
+        let newest_selection = selections[newest];
+        selections.sort_by_key(|selection| (selection.start(), selection.end()));
+
+        let mut merged: Vec<Selection> = Vec::with_capacity(selections.len());
+        let mut newest = 0;
+        for selection in selections {
+            match merged.last_mut() {
+                Some(last)
+                    if selection.start() < last.end()
+                        || selection.start() == last.end()
+                            && (selection.is_empty() || last.is_empty()) =>
+                {
+                    let (start, end) = (last.start(), last.end().max(selection.end()));
+                    *last = if last.reversed() {
+                        Selection::new(end, start)
+                    } else {
+                        Selection::new(start, end)
+                    };
+                }
+                _ => merged.push(selection),
+            }
+            if selection == newest_selection {
+                newest = merged.len() - 1;
+            }
+        }
+        Self {
+            selections: merged,
+            newest,
+        }
+    }
+
+    pub fn single(selection: Selection) -> Self {
+        Self {
+            selections: vec![selection],
+            newest: 0,
+        }
+    }
+
+    pub fn newest(&self) -> Selection {
+        self.selections[self.newest]
+    }
+
+    pub fn newest_index(&self) -> usize {
+        self.newest
+    }
+
+    pub fn len(&self) -> usize {
+        self.selections.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.selections.is_empty()
+    }
+
+    pub fn as_slice(&self) -> &[Selection] {
+        &self.selections
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Selection> {
+        self.selections.iter()
+    }
+
+    /// Applies `f` to every selection, keeping the newest one newest.
+    pub fn map(&self, f: impl FnMut(&Selection) -> Selection) -> Self {
+        Self::new(self.selections.iter().map(f).collect(), self.newest)
+    }
+
+    /// Buffer lines touched by a selection, in order. A selection ending at
+    /// the start of a line does not count that line.
+    pub fn lines(&self) -> Vec<usize> {
+        let mut lines = Vec::<usize>::new();
+        for selection in &self.selections {
+            let (start, end) = (selection.start(), selection.end());
+            let end_line = if end.line > start.line && end.column == 0 {
+                end.line - 1
+            } else {
+                end.line
+            };
+            let first = lines
+                .last()
+                .map_or(start.line, |last| start.line.max(last + 1));
+            lines.extend(first..=end_line);
+        }
+        lines
+    }
+
+    /// Moves selection ends on the given lines right (`insert`) or left by
+    /// the number of bytes added to or removed from the start of the line.
+    /// `lines` pairs line numbers, in order, with those byte counts.
+    pub fn shift_lines(&self, lines: &[(usize, usize)], insert: bool) -> Self {
+        let shift = |position: TextPosition| match lines
+            .binary_search_by_key(&position.line, |(line, _)| *line)
+        {
+            Ok(ix) if insert => TextPosition::new(position.line, position.column + lines[ix].1),
+            Ok(ix) => TextPosition::new(
+                position.line,
+                position.column - lines[ix].1.min(position.column),
+            ),
+            Err(_) => position,
+        };
+        self.map(|selection| Selection::new(shift(selection.anchor), shift(selection.head)))
+    }
+}
+
+impl Default for SelectionSet {
+    fn default() -> Self {
+        Self::single(Selection::default())
+    }
+}
+
+/// The unit a mouse drag extends the selection by, picked by the number of
+/// clicks that started it. Word and line modes remember the selection the
+/// click made, which stays selected whichever way the drag goes. Column
+/// mode, started by an alt-click, remembers the display row and x the drag
+/// started at and the selections that existed before it, which are kept
+/// only until the drag leaves the click position.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum SelectMode {
+    #[default]
+    Character,
+    Word(Selection),
+    Line(Selection),
+    Column {
+        row: usize,
+        x: Pixels,
+        base: Vec<Selection>,
+    },
+}
+
+/// Cursor and selection state of a `TextInput`, kept apart from the buffer.
+#[derive(Default)]
+pub struct EditorState {
+    pub selections: SelectionSet,
+    /// Byte range of the IME composition in the document, if any.
+    pub marked_range: Option<Range<usize>>,
+    pub is_selecting: bool,
+    pub select_mode: SelectMode,
+    /// The x each selection's head had when the current run of vertical
+    /// moves began, so that passing through shorter rows doesn't lose it.
+    /// Cleared by any other change to the selections.
+    pub goal_xs: Vec<Pixels>,
+}
+
+impl EditorState {
+    /// The newest selection, for everything that only deals with one.
+    pub fn selection(&self) -> Selection {
+        self.selections.newest()
+    }
+}
+
+/// Number of spaces that take `before`, the start of a line up to the
+/// cursor, to the next tab stop.
+pub fn soft_tab_len(before: &str, tab_width: usize) -> usize {
+    let tab_width = tab_width.max(1);
+    let (expanded, _) = TabMap::expand(before, tab_width);
+    tab_width - expanded.chars().count() % tab_width
+}
+
+/// Number of bytes a level of outdenting removes from the start of `line`:
+/// a tab, or up to `tab_width` spaces.
+pub fn outdent_len(line: &str, tab_width: usize) -> usize {
+    if line.starts_with('\t') {
+        1
+    } else {
+        line.bytes()
+            .take(tab_width)
+            .take_while(|byte| *byte == b' ')
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(column: usize) -> TextPosition {
+        TextPosition::new(0, column)
+    }
+
+    fn sel(anchor: usize, head: usize) -> Selection {
+        Selection::new(pos(anchor), pos(head))
+    }
+
+    fn lines_sel(anchor: (usize, usize), head: (usize, usize)) -> Selection {
+        Selection::new(
+            TextPosition::new(anchor.0, anchor.1),
+            TextPosition::new(head.0, head.1),
+        )
+    }
+
+    #[test]
+    fn merges_overlapping_selections() {
+        let set = SelectionSet::new(vec![sel(4, 8), sel(0, 2), sel(1, 5), sel(10, 12)], 0);
+        assert_eq!(set.as_slice(), [sel(0, 8), sel(10, 12)]);
+    }
+
+    #[test]
+    fn merges_cursors_touching_selections() {
+        let set = SelectionSet::new(vec![sel(0, 2), sel(2, 2), sel(4, 4), sel(4, 6)], 0);
+        assert_eq!(set.as_slice(), [sel(0, 2), sel(4, 6)]);
+
+        // Touching non-empty selections stay apart, as do separate cursors.
+        let set = SelectionSet::new(vec![sel(0, 2), sel(2, 4), sel(6, 6), sel(7, 7)], 0);
+        assert_eq!(set.len(), 4);
+    }
+
+    #[test]
+    fn tracks_the_newest_selection() {
+        let set = SelectionSet::new(vec![sel(6, 6), sel(0, 0), sel(3, 3)], 0);
+        assert_eq!(set.as_slice(), [sel(0, 0), sel(3, 3), sel(6, 6)]);
+        assert_eq!(set.newest(), sel(6, 6));
+
+        // When the newest selection is merged, the merged one is newest.
+        let set = SelectionSet::new(vec![sel(0, 0), sel(4, 6), sel(5, 8)], 2);
+        assert_eq!(set.newest_index(), 1);
+        assert_eq!(set.newest(), sel(4, 8));
+    }
+
+    #[test]
+    fn keeps_reversed_selections_reversed() {
+        let set = SelectionSet::new(vec![sel(9, 7), sel(3, 0)], 1);
+        assert_eq!(set.as_slice(), [sel(3, 0), sel(9, 7)]);
+        assert!(set.newest().reversed());
+
+        let set = SelectionSet::new(vec![sel(5, 0), sel(3, 8)], 0);
+        assert_eq!(set.as_slice(), [sel(8, 0)]);
+        assert!(set.newest().reversed());
+    }
+
+    #[test]
+    fn selected_lines_skip_a_line_the_selection_ends_at_the_start_of() {
+        let set = SelectionSet::new(
+            vec![
+                lines_sel((0, 2), (2, 0)),
+                lines_sel((2, 1), (2, 1)),
+                lines_sel((4, 3), (5, 0)),
+            ],
+            0,
+        );
+        assert_eq!(set.lines(), [0, 1, 2, 4]);
+
+        // An empty selection at the start of a line still counts it.
+        let set = SelectionSet::single(lines_sel((3, 0), (3, 0)));
+        assert_eq!(set.lines(), [3]);
+    }
+
+    #[test]
+    fn shifting_lines_moves_only_selection_ends_on_them() {
+        let set = SelectionSet::single(lines_sel((0, 2), (2, 0)));
+        let indented = set.shift_lines(&[(0, 4), (1, 4)], true);
+        assert_eq!(indented.newest(), lines_sel((0, 6), (2, 0)));
+
+        // Outdenting never moves an end past the start of its line.
+        let set = SelectionSet::single(lines_sel((0, 1), (1, 5)));
+        let outdented = set.shift_lines(&[(0, 2), (1, 2)], false);
+        assert_eq!(outdented.newest(), lines_sel((0, 0), (1, 3)));
+    }
+
+    #[test]
+    fn outdent_removes_a_tab_or_up_to_a_tab_width_of_spaces() {
+        assert_eq!(outdent_len("\t  x", 4), 1);
+        assert_eq!(outdent_len("      x", 4), 4);
+        assert_eq!(outdent_len("  x", 4), 2);
+        assert_eq!(outdent_len(" \tx", 4), 1);
+        assert_eq!(outdent_len("x", 4), 0);
+    }
+
+    #[test]
+    fn soft_tabs_pad_to_the_next_tab_stop() {
+        assert_eq!(soft_tab_len("", 4), 4);
+        assert_eq!(soft_tab_len("ab", 4), 2);
+        assert_eq!(soft_tab_len("abcd", 4), 4);
+        assert_eq!(soft_tab_len("a\tb", 4), 3);
+        assert_eq!(soft_tab_len("é", 4), 3);
+    }
+}
@@ -0,0 +1,112 @@
+use pulldown_cmark::{html, Options, Parser};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The markdown extensions understood on top of CommonMark.
+pub const OPTIONS: Options = Options::ENABLE_TABLES
+    .union(Options::ENABLE_STRIKETHROUGH)
+    .union(Options::ENABLE_TASKLISTS)
+    .union(Options::ENABLE_FOOTNOTES);
+
+const STYLE: &str = r#"
+body {
+    max-width: 46em;
+    margin: 2em auto;
+    padding: 0 1em;
+    font: 16px/1.6 -apple-system, BlinkMacSystemFont, "Segoe UI", Helvetica, Arial, sans-serif;
+    color: #24292e;
+}
+h1, h2 { border-bottom: 1px solid #eaecef; padding-bottom: 0.3em; }
+a { color: #0b62d6; }
+code, pre { font-family: Menlo, Consolas, monospace; font-size: 0.9em; background: #f6f8fa; }
+code { padding: 0.1em 0.3em; border-radius: 3px; }
+pre { padding: 1em; overflow: auto; border-radius: 4px; }
+pre code { padding: 0; background: none; }
+blockquote { margin: 0; padding: 0 1em; color: #6a737d; border-left: 4px solid #dddddd; }
+table { border-collapse: collapse; }
+th, td { border: 1px solid #dddddd; padding: 0.3em 0.8em; }
+th { background: #f6f8fa; }
+hr { border: none; border-top: 1px solid #dddddd; }
+img { max-width: 100%; }
+"#;
+
+/// Converts `markdown` to a standalone HTML document with `title` and an
+/// embedded stylesheet, so that the file can be opened on its own.
+pub fn to_html(markdown: &str, title: &str) -> String {
+    let mut body = String::new();
+    html::push_html(&mut body, Parser::new_ext(markdown, OPTIONS));
+    format!(
+        "<!DOCTYPE html>\n\
+         <html>\n\
+         <head>\n\
+         <meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{}</title>\n\
+         <style>{STYLE}</style>\n\
+         </head>\n\
+         <body>\n\
+         {body}\
+         </body>\n\
+         </html>\n",
+        escape(title)
+    )
+}
+
+/// Converts the markdown file at `input` to an HTML file at `output`, titled
+/// after the input file.
+pub fn export_html(input: &Path, output: &Path) -> io::Result<()> {
+    let markdown = fs::read_to_string(input)?;
+    let title = input
+        .file_stem()
+        .map_or("Untitled".into(), |stem| stem.to_string_lossy());
+    fs::write(output, to_html(&markdown, &title))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_html_wraps_the_body_in_a_standalone_page() {
+        let html = to_html("# Notes\n\nSome *text*.\n", "a <b> & \"c\"");
+        assert!(html.starts_with("<!DOCTYPE html>\n"));
+        assert!(html.contains("<title>a &lt;b&gt; &amp; &quot;c&quot;</title>"));
+        assert!(html.contains("<style>"));
+        assert!(html.contains("<h1>Notes</h1>\n<p>Some <em>text</em>.</p>\n</body>"));
+    }
+
+    #[test]
+    fn to_html_supports_the_extensions() {
+        let html = to_html("| a |\n|---|\n| 1 |\n\n~~old~~\n\n- [x] done\n", "t");
+        assert!(html.contains("<table>"));
+        assert!(html.contains("<td>1</td>"));
+        assert!(html.contains("<del>old</del>"));
+        assert!(html.contains("<input disabled=\"\" type=\"checkbox\" checked=\"\"/>"));
+    }
+
+    #[test]
+    fn export_html_titles_the_page_after_the_input() {
+        let dir = std::env::temp_dir().join(format!("gpu-md-export-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("notes.md");
+        let output = dir.join("notes.html");
+        fs::write(&input, "Hello\n").unwrap();
+
+        export_html(&input, &output).unwrap();
+        let html = fs::read_to_string(&output).unwrap();
+        assert!(html.contains("<title>notes</title>"));
+        assert!(html.contains("<p>Hello</p>"));
+
+        let missing = dir.join("missing.md");
+        assert!(export_html(&missing, &output).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
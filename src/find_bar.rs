@@ -0,0 +1,232 @@
+use crate::search::{SearchOptions, SearchQuery};
+use crate::text_input::{FindNext, FindPrevious, SelectAll, TextInput, TextInputEvent};
+use gpui::*;
+
+actions!(
+    find_bar,
+    [
+        Find,
+        Replace,
+        CloseFind,
+        ReplaceNext,
+        ReplaceAll,
+        ToggleCaseSensitive,
+        ToggleWholeWord,
+        ToggleRegex
+    ]
+);
+
+/// A search field for a `TextInput`, shown above it by the host view. The
+/// query is typed into a `TextInput` of its own, and every edit to it
+/// searches the editor again. In replace mode a second field holds the
+/// replacement text.
+pub struct FindBar {
+    pub editor: View<TextInput>,
+    pub query: View<TextInput>,
+    pub replacement: View<TextInput>,
+    pub options: SearchOptions,
+    pub visible: bool,
+    pub replace_visible: bool,
+    _subscriptions: Vec<Subscription>,
+}
+
+impl FindBar {
+    pub fn new(editor: View<TextInput>, cx: &mut ViewContext<Self>) -> Self {
+        let query = cx.new_view(|cx| TextInput::new(cx).with_markdown(false));
+        let replacement = cx.new_view(|cx| TextInput::new(cx).with_markdown(false));
+        let _subscriptions = vec![
+            cx.subscribe(&query, |this, _, event, cx| {
+                if let TextInputEvent::Edited { .. } = event {
+                    this.search(cx);
+                }
+            }),
+            // The match count changes as the document is edited.
+            cx.observe(&editor, |_, _, cx| cx.notify()),
+        ];
+        Self {
+            editor,
+            query,
+            replacement,
+            options: SearchOptions::default(),
+            visible: false,
+            replace_visible: false,
+            _subscriptions,
+        }
+    }
+
+    /// Shows the bar and focuses the query, searching for it again in case
+    /// the bar was closed since.
+    pub fn show(&mut self, cx: &mut ViewContext<Self>) {
+        self.visible = true;
+        self.search(cx);
+        self.query
+            .update(cx, |query, cx| query.select_all(&SelectAll, cx));
+        cx.focus_view(&self.query);
+        cx.notify();
+    }
+
+    /// Shows the bar with the replacement field.
+    pub fn show_replace(&mut self, cx: &mut ViewContext<Self>) {
+        self.replace_visible = true;
+        self.show(cx);
+    }
+
+    /// Hides the bar, clears the highlighted matches and hands focus back to
+    /// the editor.
+    pub fn close(&mut self, _: &CloseFind, cx: &mut ViewContext<Self>) {
+        self.visible = false;
+        self.replace_visible = false;
+        self.editor
+            .update(cx, |editor, cx| editor.set_search(None, cx));
+        cx.focus_view(&self.editor);
+        cx.notify();
+    }
+
+    pub fn toggle_case_sensitive(&mut self, _: &ToggleCaseSensitive, cx: &mut ViewContext<Self>) {
+        self.options.case_sensitive = !self.options.case_sensitive;
+        self.search(cx);
+    }
+
+    pub fn toggle_whole_word(&mut self, _: &ToggleWholeWord, cx: &mut ViewContext<Self>) {
+        self.options.whole_word = !self.options.whole_word;
+        self.search(cx);
+    }
+
+    pub fn toggle_regex(&mut self, _: &ToggleRegex, cx: &mut ViewContext<Self>) {
+        self.options.regex = !self.options.regex;
+        self.search(cx);
+    }
+
+    pub fn replace_next(&mut self, _: &ReplaceNext, cx: &mut ViewContext<Self>) {
+        let replacement = self.replacement.read(cx).to_string();
+        self.editor
+            .update(cx, |editor, cx| editor.replace_next_match(&replacement, cx));
+    }
+
+    pub fn replace_all(&mut self, _: &ReplaceAll, cx: &mut ViewContext<Self>) {
+        let replacement = self.replacement.read(cx).to_string();
+        self.editor.update(cx, |editor, cx| {
+            editor.replace_all_matches(&replacement, cx)
+        });
+    }
+
+    pub fn find_next(&mut self, action: &FindNext, cx: &mut ViewContext<Self>) {
+        self.editor
+            .update(cx, |editor, cx| editor.find_next(action, cx));
+    }
+
+    pub fn find_previous(&mut self, action: &FindPrevious, cx: &mut ViewContext<Self>) {
+        self.editor
+            .update(cx, |editor, cx| editor.find_previous(action, cx));
+    }
+
+    fn search(&mut self, cx: &mut ViewContext<Self>) {
+        let query = SearchQuery::new(&self.query.read(cx).to_string(), self.options);
+        self.editor
+            .update(cx, |editor, cx| editor.set_search(query, cx));
+        cx.notify();
+    }
+
+    fn match_label(&self, cx: &AppContext) -> String {
+        let editor = self.editor.read(cx);
+        match (editor.search(), editor.active_match()) {
+            (None, _) if self.options.regex && !self.query.read(cx).document.buffer.is_empty() => {
+                "Invalid pattern".into()
+            }
+            (None, _) => String::new(),
+            (Some(_), _) if editor.match_count() == 0 => "No results".into(),
+            (Some(_), Some(ix)) => format!("{} of {}", ix + 1, editor.match_count()),
+            (Some(_), None) => format!("{} matches", editor.match_count()),
+        }
+    }
+}
+
+/// A small clickable label, highlighted while `enabled`.
+fn button(
+    label: &'static str,
+    enabled: bool,
+    on_click: impl Fn(&MouseDownEvent, &mut WindowContext) + 'static,
+) -> Div {
+    div()
+        .px_1()
+        .rounded_sm()
+        .when(enabled, |button| button.bg(rgb(0xccccff)))
+        .on_mouse_down(MouseButton::Left, on_click)
+        .child(label)
+}
+
+fn field(input: &View<TextInput>) -> Div {
+    div().flex_1().h_full().bg(white()).child(input.clone())
+}
+
+impl Render for FindBar {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        if !self.visible {
+            return div();
+        }
+
+        let find_row = div()
+            .flex()
+            .flex_row()
+            .items_center()
+            .gap_2()
+            .h(px(28.))
+            .child(field(&self.query))
+            .child(self.match_label(cx))
+            .child(button(
+                "Aa",
+                self.options.case_sensitive,
+                cx.listener(|this, _, cx| this.toggle_case_sensitive(&ToggleCaseSensitive, cx)),
+            ))
+            .child(button(
+                "W",
+                self.options.whole_word,
+                cx.listener(|this, _, cx| this.toggle_whole_word(&ToggleWholeWord, cx)),
+            ))
+            .child(button(
+                ".*",
+                self.options.regex,
+                cx.listener(|this, _, cx| this.toggle_regex(&ToggleRegex, cx)),
+            ));
+        // The replacement field gets a key context of its own so that enter
+        // replaces there instead of finding.
+        let replace_row = div()
+            .key_context("ReplaceField")
+            .flex()
+            .flex_row()
+            .items_center()
+            .gap_2()
+            .h(px(28.))
+            .child(field(&self.replacement))
+            .child(button(
+                "Replace",
+                false,
+                cx.listener(|this, _, cx| this.replace_next(&ReplaceNext, cx)),
+            ))
+            .child(button(
+                "All",
+                false,
+                cx.listener(|this, _, cx| this.replace_all(&ReplaceAll, cx)),
+            ));
+
+        div()
+            .key_context("FindBar")
+            .on_action(cx.listener(Self::close))
+            .on_action(cx.listener(Self::toggle_case_sensitive))
+            .on_action(cx.listener(Self::toggle_whole_word))
+            .on_action(cx.listener(Self::toggle_regex))
+            .on_action(cx.listener(Self::replace_next))
+            .on_action(cx.listener(Self::replace_all))
+            .on_action(cx.listener(Self::find_next))
+            .on_action(cx.listener(Self::find_previous))
+            .flex()
+            .flex_col()
+            .px_2()
+            .border_b_1()
+            .border_color(rgb(0xdddddd))
+            .bg(rgb(0xf5f5f5))
+            .text_size(px(12.))
+            .child(find_row)
+            .when(self.replace_visible, |bar| bar.child(replace_row))
+    }
+}
@@ -0,0 +1,195 @@
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+/// Edits made within this interval of each other are undone together.
+const GROUP_INTERVAL: Duration = Duration::from_millis(300);
+
+/// A single replacement in the buffer. `range` is the replaced range in the
+/// text as it was before the edit.
+#[derive(Clone, Debug)]
+pub struct Edit {
+    pub range: Range<usize>,
+    pub old_text: String,
+    pub new_text: String,
+}
+
+impl Edit {
+    /// The range `new_text` occupies once the edit has been applied.
+    pub fn new_range(&self) -> Range<usize> {
+        self.range.start..self.range.start + self.new_text.len()
+    }
+}
+
+/// A group of edits that is undone and redone as one step, together with the
+/// selection to restore on either side of it.
+pub struct Transaction<S> {
+    pub edits: Vec<Edit>,
+    pub selection_before: S,
+    pub selection_after: Option<S>,
+    last_edited_at: Instant,
+}
+
+pub struct History<S> {
+    undo_stack: Vec<Transaction<S>>,
+    redo_stack: Vec<Transaction<S>>,
+    group_interval: Duration,
+    last_transaction_finalized: bool,
+}
+
+impl<S: Clone> History<S> {
+    pub fn new() -> Self {
+        Self {
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            group_interval: GROUP_INTERVAL,
+            last_transaction_finalized: true,
+        }
+    }
+
+    /// Records an edit, appending it to the last transaction when it
+    /// continues the same burst of typing.
+    pub fn push(&mut self, edit: Edit, selection_before: S, now: Instant) {
+        self.redo_stack.clear();
+        match self.undo_stack.last_mut() {
+            Some(transaction)
+                if !self.last_transaction_finalized
+                    && now.duration_since(transaction.last_edited_at) < self.group_interval =>
+            {
+                transaction.edits.push(edit);
+                transaction.last_edited_at = now;
+            }
+            _ => self.undo_stack.push(Transaction {
+                edits: vec![edit],
+                selection_before,
+                selection_after: None,
+                last_edited_at: now,
+            }),
+        }
+        self.last_transaction_finalized = false;
+    }
+
+    /// Prevents the next edit from being grouped with the previous ones.
+    pub fn finalize_last_transaction(&mut self) {
+        self.last_transaction_finalized = true;
+    }
+
+    /// Moves the last transaction onto the redo stack and returns it so the
+    /// caller can revert its edits, last to first.
+    pub fn undo(&mut self, selection: S) -> Option<&Transaction<S>> {
+        let mut transaction = self.undo_stack.pop()?;
+        transaction.selection_after = Some(selection);
+        self.redo_stack.push(transaction);
+        self.last_transaction_finalized = true;
+        self.redo_stack.last()
+    }
+
+    /// Moves the last undone transaction back onto the undo stack and returns
+    /// it so the caller can reapply its edits, first to last.
+    pub fn redo(&mut self) -> Option<&Transaction<S>> {
+        let transaction = self.redo_stack.pop()?;
+        self.undo_stack.push(transaction);
+        self.last_transaction_finalized = true;
+        self.undo_stack.last()
+    }
+}
+
+impl<S: Clone> Default for History<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(at: usize, text: &str) -> Edit {
+        Edit {
+            range: at..at,
+            old_text: String::new(),
+            new_text: text.to_owned(),
+        }
+    }
+
+    fn edit_counts(history: &History<usize>) -> Vec<usize> {
+        history
+            .undo_stack
+            .iter()
+            .map(|transaction| transaction.edits.len())
+            .collect()
+    }
+
+    #[test]
+    fn groups_edits_within_the_interval() {
+        let mut history = History::new();
+        let start = Instant::now();
+        history.push(insert(0, "a"), 0, start);
+        history.push(insert(1, "b"), 1, start + Duration::from_millis(100));
+        history.push(insert(2, "c"), 2, start + Duration::from_millis(350));
+        assert_eq!(edit_counts(&history), [3]);
+
+        history.push(insert(3, "d"), 3, start + Duration::from_millis(700));
+        assert_eq!(edit_counts(&history), [3, 1]);
+    }
+
+    #[test]
+    fn finalize_starts_a_new_transaction() {
+        let mut history = History::new();
+        let now = Instant::now();
+        history.push(insert(0, "a"), 0, now);
+        history.finalize_last_transaction();
+        history.push(insert(5, "b"), 5, now);
+        assert_eq!(edit_counts(&history), [1, 1]);
+        assert_eq!(history.undo_stack[1].selection_before, 5);
+    }
+
+    #[test]
+    fn keeps_the_selection_from_before_the_first_edit() {
+        let mut history = History::new();
+        let now = Instant::now();
+        history.push(insert(0, "a"), 7, now);
+        history.push(insert(1, "b"), 8, now);
+
+        let transaction = history.undo(2).unwrap();
+        assert_eq!(transaction.selection_before, 7);
+        assert_eq!(transaction.selection_after, Some(2));
+    }
+
+    #[test]
+    fn undo_and_redo_move_transactions_between_stacks() {
+        let mut history = History::new();
+        let now = Instant::now();
+        history.push(insert(0, "a"), 0, now);
+        history.finalize_last_transaction();
+        history.push(insert(1, "b"), 1, now);
+
+        assert_eq!(history.undo(2).unwrap().edits[0].new_text, "b");
+        assert_eq!(history.undo(1).unwrap().edits[0].new_text, "a");
+        assert!(history.undo(0).is_none());
+
+        assert_eq!(history.redo().unwrap().edits[0].new_text, "a");
+        assert_eq!(history.redo().unwrap().edits[0].new_text, "b");
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn redone_transactions_are_not_grouped_with_new_edits() {
+        let mut history = History::new();
+        let now = Instant::now();
+        history.push(insert(0, "a"), 0, now);
+        history.undo(1);
+        history.redo();
+        history.push(insert(1, "b"), 1, now);
+        assert_eq!(edit_counts(&history), [1, 1]);
+    }
+
+    #[test]
+    fn a_new_edit_clears_the_redo_stack() {
+        let mut history = History::new();
+        let now = Instant::now();
+        history.push(insert(0, "a"), 0, now);
+        history.undo(1);
+        history.push(insert(0, "b"), 0, now);
+        assert!(history.redo().is_none());
+    }
+}
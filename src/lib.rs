@@ -0,0 +1,79 @@
+pub mod buffer;
+pub mod display_map;
+pub mod editor_state;
+pub mod export;
+pub mod find_bar;
+pub mod history;
+pub mod markdown;
+pub mod preview;
+pub mod search;
+pub mod text_element;
+pub mod text_input;
+
+use find_bar::*;
+use gpui::{AppContext, KeyBinding};
+use text_input::*;
+
+/// Binds the standard editing keys for every `TextInput` in the app. The
+/// bindings only apply inside the `TextInput` key context, so they don't
+/// clash with the host application's own shortcuts.
+pub fn register_default_keybindings(cx: &mut AppContext) {
+    let context = Some("TextInput");
+    cx.bind_keys([
+        KeyBinding::new("backspace", Backspace, context),
+        KeyBinding::new("delete", Delete, context),
+        KeyBinding::new("left", Left, context),
+        KeyBinding::new("right", Right, context),
+        KeyBinding::new("shift-left", SelectLeft, context),
+        KeyBinding::new("shift-right", SelectRight, context),
+        KeyBinding::new("alt-left", MoveWordLeft, context),
+        KeyBinding::new("alt-right", MoveWordRight, context),
+        KeyBinding::new("alt-shift-left", SelectWordLeft, context),
+        KeyBinding::new("alt-shift-right", SelectWordRight, context),
+        KeyBinding::new("alt-backspace", DeleteWordBackward, context),
+        KeyBinding::new("alt-delete", DeleteWordForward, context),
+        KeyBinding::new("cmd-a", SelectAll, context),
+        KeyBinding::new("ctrl-d", SelectNextOccurrence, context),
+        KeyBinding::new("escape", Cancel, context),
+        KeyBinding::new("home", Home, context),
+        KeyBinding::new("end", End, context),
+        KeyBinding::new("ctrl-cmd-space", ShowCharacterPalette, context),
+        KeyBinding::new("enter", Enter, context),
+        KeyBinding::new("tab", Tab, context),
+        KeyBinding::new("shift-tab", Outdent, context),
+        KeyBinding::new("cmd-]", Indent, context),
+        KeyBinding::new("cmd-[", Outdent, context),
+        KeyBinding::new("up", Up, context),
+        KeyBinding::new("down", Down, context),
+        KeyBinding::new("shift-up", SelectUp, context),
+        KeyBinding::new("shift-down", SelectDown, context),
+        KeyBinding::new("pageup", PageUp, context),
+        KeyBinding::new("pagedown", PageDown, context),
+        KeyBinding::new("shift-pageup", SelectPageUp, context),
+        KeyBinding::new("shift-pagedown", SelectPageDown, context),
+        KeyBinding::new("shift-home", SelectToLineStart, context),
+        KeyBinding::new("shift-end", SelectToLineEnd, context),
+        KeyBinding::new("cmd-up", MoveToDocumentStart, context),
+        KeyBinding::new("cmd-down", MoveToDocumentEnd, context),
+        KeyBinding::new("cmd-shift-up", SelectToDocumentStart, context),
+        KeyBinding::new("cmd-shift-down", SelectToDocumentEnd, context),
+        KeyBinding::new("cmd-z", Undo, context),
+        KeyBinding::new("cmd-shift-z", Redo, context),
+        KeyBinding::new("cmd-c", Copy, context),
+        KeyBinding::new("cmd-x", Cut, context),
+        KeyBinding::new("cmd-v", Paste, context),
+        KeyBinding::new("cmd-f", Find, context),
+        KeyBinding::new("alt-cmd-f", Replace, context),
+        KeyBinding::new("cmd-g", FindNext, context),
+        KeyBinding::new("cmd-shift-g", FindPrevious, context),
+        // Later bindings win, so these override the query input's own keys.
+        KeyBinding::new("enter", FindNext, Some("FindBar > TextInput")),
+        KeyBinding::new("shift-enter", FindPrevious, Some("FindBar > TextInput")),
+        KeyBinding::new("enter", ReplaceNext, Some("ReplaceField > TextInput")),
+        KeyBinding::new("cmd-enter", ReplaceAll, Some("ReplaceField > TextInput")),
+        KeyBinding::new("escape", CloseFind, Some("FindBar")),
+        KeyBinding::new("alt-cmd-c", ToggleCaseSensitive, Some("FindBar")),
+        KeyBinding::new("alt-cmd-w", ToggleWholeWord, Some("FindBar")),
+        KeyBinding::new("alt-cmd-r", ToggleRegex, Some("FindBar")),
+    ]);
+}
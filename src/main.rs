@@ -1,39 +1,221 @@
-mod input_example;
-mod text_element;
-mod text_input;
-
+use gpu_md::export::{export_html, to_html};
+use gpu_md::find_bar::{Find, FindBar, Replace};
+use gpu_md::preview::MarkdownPreview;
+use gpu_md::register_default_keybindings;
+use gpu_md::text_input::TextInput;
 use gpui::*;
-use input_example::InputExample;
-use text_input::TextInput;
-use text_input::TextLine;
-use text_input::{
-    Backspace, Delete, Down, End, Enter, Home, Left, Right, SelectAll, SelectLeft, SelectRight,
-    ShowCharacterPalette, Up,
-};
+use rfd::{FileDialog, MessageButtons, MessageDialog, MessageDialogResult, MessageLevel};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+actions!(workspace, [Open, Save, SaveAs, ExportHtml, Quit]);
+
+/// The editor window: the find bar above the input, with the markdown
+/// preview beside it.
+pub struct Workspace {
+    pub text_input: View<TextInput>,
+    pub find_bar: View<FindBar>,
+    pub preview: View<MarkdownPreview>,
+    pub focus_handle: FocusHandle,
+}
+
+impl Workspace {
+    fn open(&mut self, _: &Open, cx: &mut ViewContext<Self>) {
+        if !self.confirm_discard(cx) {
+            return;
+        }
+        let Some(path) = FileDialog::new()
+            .add_filter("Markdown", &["md", "markdown"])
+            .add_filter("Text", &["txt"])
+            .pick_file()
+        else {
+            return;
+        };
+        let result = self
+            .text_input
+            .update(cx, |text_input, cx| text_input.load(path.clone(), cx));
+        if let Err(error) = result {
+            show_file_error("open", &path, error);
+        }
+    }
+
+    fn save(&mut self, _: &Save, cx: &mut ViewContext<Self>) {
+        match self.text_input.read(cx).path.clone() {
+            Some(path) => self.write_to(path, cx),
+            None => self.save_as(&SaveAs, cx),
+        }
+    }
+
+    fn save_as(&mut self, _: &SaveAs, cx: &mut ViewContext<Self>) {
+        let file_name = self
+            .text_input
+            .read(cx)
+            .path
+            .as_ref()
+            .and_then(|path| path.file_name())
+            .map_or("Untitled.md".into(), |name| name.to_string_lossy());
+        let Some(path) = FileDialog::new()
+            .add_filter("Markdown", &["md", "markdown"])
+            .set_file_name(file_name)
+            .save_file()
+        else {
+            return;
+        };
+        self.write_to(path, cx);
+    }
+
+    fn write_to(&mut self, path: PathBuf, cx: &mut ViewContext<Self>) {
+        let result = self
+            .text_input
+            .update(cx, |text_input, cx| text_input.write_to(path.clone(), cx));
+        if let Err(error) = result {
+            show_file_error("save", &path, error);
+        }
+    }
+
+    /// Writes the document as a standalone HTML file, chosen in a save
+    /// dialog.
+    fn export_html(&mut self, _: &ExportHtml, cx: &mut ViewContext<Self>) {
+        let text_input = self.text_input.read(cx);
+        let title = text_input
+            .path
+            .as_ref()
+            .and_then(|path| path.file_stem())
+            .map_or("Untitled".into(), |stem| stem.to_string_lossy());
+        let Some(path) = FileDialog::new()
+            .add_filter("HTML", &["html", "htm"])
+            .set_file_name(format!("{title}.html"))
+            .save_file()
+        else {
+            return;
+        };
+        let html = to_html(&text_input.document.buffer.to_string(), &title);
+        if let Err(error) = fs::write(&path, html) {
+            show_file_error("export", &path, error);
+        }
+    }
+
+    /// Asks the user whether unsaved changes may be thrown away. Without
+    /// unsaved changes there is nothing to ask.
+    fn confirm_discard(&self, cx: &AppContext) -> bool {
+        let text_input = self.text_input.read(cx);
+        if !text_input.is_dirty() {
+            return true;
+        }
+        let result = MessageDialog::new()
+            .set_level(MessageLevel::Warning)
+            .set_title("Discard unsaved changes?")
+            .set_description(format!(
+                "{} has changes that have not been saved.",
+                text_input.title_name()
+            ))
+            .set_buttons(MessageButtons::OkCancel)
+            .show();
+        matches!(result, MessageDialogResult::Ok)
+    }
+}
+
+fn show_file_error(action: &str, path: &Path, error: io::Error) {
+    MessageDialog::new()
+        .set_level(MessageLevel::Error)
+        .set_title(format!("Could not {action} file"))
+        .set_description(format!("{}: {error}", path.display()))
+        .show();
+}
+
+impl FocusableView for Workspace {
+    fn focus_handle(&self, _: &AppContext) -> FocusHandle {
+        self.focus_handle.clone()
+    }
+}
+
+impl Render for Workspace {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let title = self.text_input.read(cx).title();
+        cx.set_window_title(&title);
+
+        div()
+            .key_context("Workspace")
+            .on_action(cx.listener(Self::open))
+            .on_action(cx.listener(Self::save))
+            .on_action(cx.listener(Self::save_as))
+            .on_action(cx.listener(Self::export_html))
+            .on_action(cx.listener(|this, _: &Find, cx| {
+                this.find_bar.update(cx, |find_bar, cx| find_bar.show(cx))
+            }))
+            .on_action(cx.listener(|this, _: &Replace, cx| {
+                this.find_bar
+                    .update(cx, |find_bar, cx| find_bar.show_replace(cx))
+            }))
+            .flex()
+            .flex_col()
+            .size_full()
+            .child(self.find_bar.clone())
+            .child(
+                div()
+                    .flex()
+                    .flex_row()
+                    .flex_1()
+                    .child(div().flex_1().h_full().child(self.text_input.clone()))
+                    .child(
+                        div()
+                            .flex_1()
+                            .h_full()
+                            .border_l_1()
+                            .border_color(rgb(0xdddddd))
+                            .child(self.preview.clone()),
+                    ),
+            )
+            .size_full()
+            .bg(white())
+            .cursor(CursorStyle::IBeam)
+            .h_full()
+    }
+}
 
 fn main() {
+    let mut args = std::env::args_os().skip(1);
+    let path = match args.next() {
+        // Converts a file without opening a window, for use in scripts.
+        Some(flag) if flag == "--export-html" => {
+            let (Some(input), Some(output)) = (args.next(), args.next()) else {
+                eprintln!("usage: gpu-md --export-html <in.md> <out.html>");
+                std::process::exit(2);
+            };
+            let input = Path::new(&input);
+            if let Err(error) = export_html(input, Path::new(&output)) {
+                eprintln!("failed to export {}: {error}", input.display());
+                std::process::exit(1);
+            }
+            return;
+        }
+        path => path.map(PathBuf::from),
+    };
+
     App::new().run(|cx: &mut AppContext| {
         cx.activate(true);
         cx.on_action(quit);
         cx.set_menus(vec![Menu {
             name: "set_menus".into(),
-            items: vec![MenuItem::action("Quit", Quit)],
+            items: vec![
+                MenuItem::action("Open…", Open),
+                MenuItem::action("Save", Save),
+                MenuItem::action("Save As…", SaveAs),
+                MenuItem::action("Export HTML…", ExportHtml),
+                MenuItem::separator(),
+                MenuItem::action("Quit", Quit),
+            ],
         }]);
-        let bounds = Bounds::centered(None, size(px(500.0), px(500.0)), cx);
+        let bounds = Bounds::centered(None, size(px(900.0), px(600.0)), cx);
+        register_default_keybindings(cx);
+        // File handling belongs to the window rather than to any one input,
+        // so these keys also work from the find bar's fields.
+        let context = Some("Workspace");
         cx.bind_keys([
-            KeyBinding::new("backspace", Backspace, None),
-            KeyBinding::new("delete", Delete, None),
-            KeyBinding::new("left", Left, None),
-            KeyBinding::new("right", Right, None),
-            KeyBinding::new("shift-left", SelectLeft, None),
-            KeyBinding::new("shift-right", SelectRight, None),
-            KeyBinding::new("cmd-a", SelectAll, None),
-            KeyBinding::new("home", Home, None),
-            KeyBinding::new("end", End, None),
-            KeyBinding::new("ctrl-cmd-space", ShowCharacterPalette, None),
-            KeyBinding::new("enter", Enter, None),
-            KeyBinding::new("up", Up, None),
-            KeyBinding::new("down", Down, None),
+            KeyBinding::new("cmd-o", Open, context),
+            KeyBinding::new("cmd-s", Save, context),
+            KeyBinding::new("cmd-shift-s", SaveAs, context),
         ]);
         let window = cx
             .open_window(
@@ -42,21 +224,25 @@ fn main() {
                     ..Default::default()
                 },
                 |cx| {
-                    let text_input = cx.new_view(|cx| TextInput {
-                        focus_handle: cx.focus_handle(),
-                        content: vec![TextLine {
-                            content: "".into(),
-                            selected_range: 0..0,
-                            selection_reversed: false,
-                            marked_range: None,
-                            last_layout: None,
-                            is_selecting: false,
-                        }],
-                        content_idx: 0,
-                        last_bounds: None,
+                    let text_input = cx.new_view(|cx| {
+                        let mut text_input = TextInput::new(cx);
+                        match path {
+                            Some(path) if path.exists() => {
+                                if let Err(error) = text_input.load(path.clone(), cx) {
+                                    eprintln!("failed to open {}: {error}", path.display());
+                                }
+                            }
+                            Some(path) => text_input = text_input.with_path(path),
+                            None => {}
+                        }
+                        text_input
                     });
-                    cx.new_view(|cx| InputExample {
+                    let find_bar = cx.new_view(|cx| FindBar::new(text_input.clone(), cx));
+                    let preview = cx.new_view(|cx| MarkdownPreview::new(text_input.clone(), cx));
+                    cx.new_view(|cx| Workspace {
                         text_input,
+                        find_bar,
+                        preview,
                         focus_handle: cx.focus_handle(),
                     })
                 },
@@ -71,8 +257,6 @@ fn main() {
     });
 }
 
-actions!(set_menus, [Quit]);
-
 fn quit(_: &Quit, cx: &mut AppContext) {
     cx.quit();
 }
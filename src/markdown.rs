@@ -0,0 +1,569 @@
+use crate::display_map::invalidate_lines;
+use gpui::{rgb, FontStyle, FontWeight, HighlightStyle, UnderlineStyle};
+use std::ops::Range;
+
+/// The markdown syntax a span of a line belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Token {
+    Heading,
+    Emphasis,
+    Strong,
+    Code,
+    /// The text of a link, with its brackets.
+    Link,
+    /// The destination of a link, with its parentheses.
+    LinkUrl,
+    BlockQuote,
+    ListMarker,
+    /// A thematic break such as `---`.
+    Rule,
+    /// The opening or closing line of a fenced code block.
+    CodeFence,
+    CodeBlock,
+}
+
+impl Token {
+    pub fn style(self) -> HighlightStyle {
+        match self {
+            Token::Heading => HighlightStyle {
+                color: Some(rgb(0x1f4e99).into()),
+                font_weight: Some(FontWeight::BOLD),
+                ..Default::default()
+            },
+            Token::Emphasis => HighlightStyle {
+                font_style: Some(FontStyle::Italic),
+                ..Default::default()
+            },
+            Token::Strong => HighlightStyle {
+                font_weight: Some(FontWeight::BOLD),
+                ..Default::default()
+            },
+            Token::Code => HighlightStyle {
+                color: Some(rgb(0xb3261e).into()),
+                background_color: Some(rgb(0xf0f0f0).into()),
+                ..Default::default()
+            },
+            Token::Link => HighlightStyle {
+                color: Some(rgb(0x0b62d6).into()),
+                underline: Some(UnderlineStyle {
+                    color: Some(rgb(0x0b62d6).into()),
+                    thickness: gpui::px(1.),
+                    wavy: false,
+                }),
+                ..Default::default()
+            },
+            Token::LinkUrl | Token::Rule | Token::CodeFence => HighlightStyle {
+                color: Some(rgb(0x8a8a8a).into()),
+                ..Default::default()
+            },
+            Token::BlockQuote => HighlightStyle {
+                color: Some(rgb(0x6a737d).into()),
+                font_style: Some(FontStyle::Italic),
+                ..Default::default()
+            },
+            Token::ListMarker => HighlightStyle {
+                color: Some(rgb(0xc2410c).into()),
+                font_weight: Some(FontWeight::BOLD),
+                ..Default::default()
+            },
+            Token::CodeBlock => HighlightStyle {
+                color: Some(rgb(0x24292e).into()),
+                background_color: Some(rgb(0xf6f8fa).into()),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// What a line passes on to the next one: the fence of the code block it
+/// leaves open, if any.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LineState {
+    fence: Option<Fence>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Fence {
+    marker: u8,
+    len: usize,
+}
+
+struct HighlightedLine {
+    start: LineState,
+    end: LineState,
+    spans: Vec<(Range<usize>, Token)>,
+}
+
+/// Highlights markdown a line at a time. A line is highlighted again only
+/// after it was edited, or when the line above now ends in a different
+/// state, such as after a fence was opened above it.
+#[derive(Default)]
+pub struct MarkdownHighlighter {
+    lines: Vec<Option<HighlightedLine>>,
+}
+
+impl MarkdownHighlighter {
+    pub fn new(line_count: usize) -> Self {
+        Self {
+            lines: std::iter::repeat_with(|| None).take(line_count).collect(),
+        }
+    }
+
+    /// Records that `old_lines` of the buffer were replaced by
+    /// `new_line_count` lines. The next `sync` highlights the new lines and
+    /// carries on past them while the state they end in differs from before.
+    pub fn edit(&mut self, old_lines: Range<usize>, new_line_count: usize) {
+        invalidate_lines(&mut self.lines, old_lines, new_line_count);
+    }
+
+    /// Highlights every edited line and every line whose starting state
+    /// changed.
+    pub fn sync(&mut self, line_text: impl Fn(usize) -> String) {
+        let mut state = LineState::default();
+        for (line, highlighted) in self.lines.iter_mut().enumerate() {
+            match highlighted {
+                Some(highlighted) if highlighted.start == state => state = highlighted.end,
+                _ => {
+                    let (spans, end) = highlight_line(&line_text(line), state);
+                    *highlighted = Some(HighlightedLine {
+                        start: state,
+                        end,
+                        spans,
+                    });
+                    state = end;
+                }
+            }
+        }
+    }
+
+    /// Spans of `line` as byte ranges of the line. Spans may nest, and later
+    /// ones take precedence. Lines not highlighted yet have none.
+    pub fn spans(&self, line: usize) -> &[(Range<usize>, Token)] {
+        self.lines
+            .get(line)
+            .and_then(Option::as_ref)
+            .map_or(&[], |highlighted| &highlighted.spans)
+    }
+}
+
+/// Highlights one line that starts in `state`, returning its spans and the
+/// state the next line starts in.
+pub fn highlight_line(text: &str, state: LineState) -> (Vec<(Range<usize>, Token)>, LineState) {
+    let whole_line = 0..text.len();
+    let indent = text.len() - text.trim_start_matches(' ').len();
+    let trimmed = &text[indent..];
+
+    if let Some(fence) = state.fence {
+        return if indent < 4 && closes_fence(trimmed, fence) {
+            (vec![(whole_line, Token::CodeFence)], LineState::default())
+        } else {
+            (vec![(whole_line, Token::CodeBlock)], state)
+        };
+    }
+    if indent < 4 {
+        if let Some(fence) = open_fence(trimmed) {
+            return (
+                vec![(whole_line, Token::CodeFence)],
+                LineState { fence: Some(fence) },
+            );
+        }
+    }
+
+    let mut spans = Vec::new();
+    let mut start = 0;
+    // Block quote markers, possibly nested, prefix everything else.
+    loop {
+        let rest = &text[start..];
+        let indent = rest.len() - rest.trim_start_matches(' ').len();
+        if indent >= 4 || !rest[indent..].starts_with('>') {
+            break;
+        }
+        if start == 0 {
+            spans.push((whole_line.clone(), Token::BlockQuote));
+        }
+        start += indent + 1;
+    }
+    let content = &text[start..];
+    let indent = content.len() - content.trim_start_matches(' ').len();
+    let trimmed = &content[indent..];
+
+    let hashes = trimmed.bytes().take_while(|byte| *byte == b'#').count();
+    if indent < 4
+        && (1..=6).contains(&hashes)
+        && trimmed[hashes..]
+            .chars()
+            .next()
+            .is_none_or(char::is_whitespace)
+    {
+        spans.push((start..text.len(), Token::Heading));
+    } else if indent < 4 && is_thematic_break(trimmed) {
+        spans.push((start + indent..text.len(), Token::Rule));
+        return (spans, LineState::default());
+    } else if let Some(len) = list_marker_len(trimmed) {
+        let marker_start = start + indent;
+        spans.push((marker_start..marker_start + len, Token::ListMarker));
+    }
+    highlight_inline(content, start, &mut spans);
+    (spans, LineState::default())
+}
+
+fn open_fence(text: &str) -> Option<Fence> {
+    let marker = *text.as_bytes().first()?;
+    if marker != b'`' && marker != b'~' {
+        return None;
+    }
+    let len = text.bytes().take_while(|byte| *byte == marker).count();
+    // A backtick fence cannot have backticks in its info string, which is
+    // how it is told apart from inline code.
+    (len >= 3 && (marker == b'~' || !text[len..].contains('`'))).then_some(Fence { marker, len })
+}
+
+fn closes_fence(text: &str, fence: Fence) -> bool {
+    let len = text
+        .bytes()
+        .take_while(|byte| *byte == fence.marker)
+        .count();
+    len >= fence.len && text[len..].trim().is_empty()
+}
+
+/// How pressing enter on a list item continues the list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ListContinuation {
+    /// Start the next item with this prefix: the indentation, marker and
+    /// task box of the current item, with an ordered number counted up.
+    Item(String),
+    /// The item is empty, so the list ends instead.
+    End,
+}
+
+/// How pressing enter at `column` of `line` continues a list. `None` when
+/// the line is not a list item, or the cursor is before the item's text.
+pub fn list_continuation(line: &str, column: usize) -> Option<ListContinuation> {
+    let indent = line.len() - line.trim_start_matches([' ', '\t']).len();
+    let rest = &line[indent..];
+    let marker_len = list_marker_len(rest)?;
+    let (marker, after) = rest.split_at(marker_len);
+    let spacing = after.len() - after.trim_start_matches([' ', '\t']).len();
+    if spacing == 0 {
+        return None;
+    }
+    let mut prefix_len = indent + marker_len + spacing;
+    let task = ["[ ] ", "[x] ", "[X] "]
+        .iter()
+        .any(|task| line[prefix_len..].starts_with(task));
+    if task {
+        prefix_len += 4;
+    }
+
+    if column < prefix_len {
+        None
+    } else if line[prefix_len..].trim().is_empty() {
+        Some(ListContinuation::End)
+    } else {
+        let (number, delimiter) = marker.split_at(marker_len - 1);
+        let marker = match number.parse::<u64>() {
+            Ok(number) => format!("{}{delimiter}", number + 1),
+            Err(_) => marker.to_owned(),
+        };
+        Some(ListContinuation::Item(format!(
+            "{}{marker}{}{}",
+            &line[..indent],
+            &after[..spacing],
+            if task { "[ ] " } else { "" }
+        )))
+    }
+}
+
+/// Length of the bullet or number that starts a list item, if `text` is one.
+/// A thematic break such as `* * *` is not a list item, even though it
+/// starts with a bullet.
+fn list_marker_len(text: &str) -> Option<usize> {
+    if is_thematic_break(text) {
+        return None;
+    }
+    let bytes = text.as_bytes();
+    let len = match bytes.first()? {
+        b'-' | b'*' | b'+' => 1,
+        b'0'..=b'9' => {
+            let digits = bytes
+                .iter()
+                .take_while(|byte| byte.is_ascii_digit())
+                .count();
+            if digits > 9 || !matches!(bytes.get(digits), Some(b'.' | b')')) {
+                return None;
+            }
+            digits + 1
+        }
+        _ => return None,
+    };
+    matches!(bytes.get(len), None | Some(b' ' | b'\t')).then_some(len)
+}
+
+/// Whether `text`, with its indentation removed, is a thematic break: three
+/// or more `-`, `*` or `_` of the same kind, optionally separated by spaces
+/// or tabs.
+fn is_thematic_break(text: &str) -> bool {
+    let Some(marker @ (b'-' | b'*' | b'_')) = text.bytes().next() else {
+        return false;
+    };
+    let mut count = 0;
+    for byte in text.trim_end().bytes() {
+        match byte {
+            b' ' | b'\t' => {}
+            byte if byte == marker => count += 1,
+            _ => return false,
+        }
+    }
+    count >= 3
+}
+
+/// Adds spans for the inline syntax of `text`, which starts at `offset` of
+/// the line. All delimiters are ASCII, so byte indices are char boundaries
+/// wherever a delimiter was found.
+fn highlight_inline(text: &str, offset: usize, spans: &mut Vec<(Range<usize>, Token)>) {
+    let bytes = text.as_bytes();
+    let mut ix = 0;
+    while ix < bytes.len() {
+        match bytes[ix] {
+            b'\\' => ix += 2,
+            b'`' => {
+                let len = run_len(bytes, ix);
+                match find_code_end(bytes, ix + len, len) {
+                    Some(end) => {
+                        spans.push((offset + ix..offset + end, Token::Code));
+                        ix = end;
+                    }
+                    None => ix += len,
+                }
+            }
+            b'[' => match link_end(bytes, ix) {
+                Some((text_end, end)) => {
+                    spans.push((offset + ix..offset + text_end, Token::Link));
+                    spans.push((offset + text_end..offset + end, Token::LinkUrl));
+                    highlight_inline(&text[ix + 1..text_end - 1], offset + ix + 1, spans);
+                    ix = end;
+                }
+                None => ix += 1,
+            },
+            marker @ (b'*' | b'_') => {
+                let len = run_len(bytes, ix);
+                let (delimiter, token) = if len >= 2 {
+                    (2, Token::Strong)
+                } else {
+                    (1, Token::Emphasis)
+                };
+                let inner = ix + delimiter;
+                let intraword = marker == b'_' && ix > 0 && bytes[ix - 1].is_ascii_alphanumeric();
+                let close = (!intraword
+                    && bytes
+                        .get(inner)
+                        .is_some_and(|byte| !byte.is_ascii_whitespace()))
+                .then(|| find_closing(bytes, inner, marker, delimiter))
+                .flatten();
+                match close {
+                    Some(close) => {
+                        spans.push((offset + ix..offset + close + delimiter, token));
+                        highlight_inline(&text[inner..close], offset + inner, spans);
+                        ix = close + delimiter;
+                    }
+                    None => ix += len,
+                }
+            }
+            _ => ix += 1,
+        }
+    }
+}
+
+fn run_len(bytes: &[u8], ix: usize) -> usize {
+    bytes[ix..]
+        .iter()
+        .take_while(|byte| **byte == bytes[ix])
+        .count()
+}
+
+/// End of the code span whose contents start at `from`: just past a run of
+/// exactly `len` backticks.
+fn find_code_end(bytes: &[u8], from: usize, len: usize) -> Option<usize> {
+    let mut ix = from;
+    while ix < bytes.len() {
+        if bytes[ix] == b'`' {
+            let run = run_len(bytes, ix);
+            if run == len {
+                return Some(ix + run);
+            }
+            ix += run;
+        } else {
+            ix += 1;
+        }
+    }
+    None
+}
+
+/// Start of the `delimiter` long run of `marker` that closes emphasis
+/// opened before `from`. A closing run follows non-whitespace and, for
+/// underscores, is not followed by a letter or digit.
+fn find_closing(bytes: &[u8], from: usize, marker: u8, delimiter: usize) -> Option<usize> {
+    let mut ix = from;
+    while ix < bytes.len() {
+        match bytes[ix] {
+            b'\\' => ix += 2,
+            byte if byte == marker => {
+                let run = run_len(bytes, ix);
+                let closes = !bytes[ix - 1].is_ascii_whitespace()
+                    && (run == delimiter || (delimiter == 2 && run > 2))
+                    && (marker != b'_'
+                        || !bytes
+                            .get(ix + run)
+                            .is_some_and(|byte| byte.is_ascii_alphanumeric()));
+                if closes {
+                    // Of a longer run, the last two close strong emphasis and
+                    // the rest belong to emphasis inside it.
+                    return Some(ix + run - delimiter);
+                }
+                ix += run;
+            }
+            _ => ix += 1,
+        }
+    }
+    None
+}
+
+/// For a `[text](url)` link at `start`, the end of the bracketed text and
+/// the end of the whole link.
+fn link_end(bytes: &[u8], start: usize) -> Option<(usize, usize)> {
+    let text_end = start + bytes[start..].iter().position(|byte| *byte == b']')? + 1;
+    if bytes.get(text_end) != Some(&b'(') {
+        return None;
+    }
+    let end = text_end + bytes[text_end..].iter().position(|byte| *byte == b')')? + 1;
+    Some((text_end, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(prefix: &str) -> Option<ListContinuation> {
+        Some(ListContinuation::Item(prefix.to_owned()))
+    }
+
+    #[test]
+    fn continues_bullet_and_ordered_items() {
+        assert_eq!(list_continuation("- one", 5), item("- "));
+        assert_eq!(list_continuation("+   wide", 8), item("+   "));
+        assert_eq!(list_continuation("1. one", 6), item("2. "));
+        assert_eq!(list_continuation("9. nine", 7), item("10. "));
+        assert_eq!(list_continuation("3) three", 8), item("4) "));
+    }
+
+    #[test]
+    fn continues_indented_items() {
+        assert_eq!(list_continuation("  * nested", 10), item("  * "));
+        assert_eq!(list_continuation("\t2. tabbed", 10), item("\t3. "));
+    }
+
+    #[test]
+    fn continues_task_items_unchecked() {
+        assert_eq!(list_continuation("- [x] done", 10), item("- [ ] "));
+        assert_eq!(list_continuation("1. [ ] todo", 11), item("2. [ ] "));
+    }
+
+    #[test]
+    fn empty_items_end_the_list() {
+        assert_eq!(list_continuation("- ", 2), Some(ListContinuation::End));
+        assert_eq!(list_continuation("  1.  ", 6), Some(ListContinuation::End));
+        assert_eq!(list_continuation("- [ ] ", 6), Some(ListContinuation::End));
+    }
+
+    #[test]
+    fn only_list_items_continue() {
+        assert_eq!(list_continuation("plain text", 10), None);
+        assert_eq!(list_continuation("-not a list", 11), None);
+        assert_eq!(list_continuation("1.5 million", 11), None);
+        // The cursor is before the item's text.
+        assert_eq!(list_continuation("- one", 1), None);
+    }
+
+    #[test]
+    fn thematic_breaks_are_not_list_items() {
+        for rule in [
+            "* * *",
+            "- - -",
+            "***",
+            "---",
+            "___",
+            "*  *  *  ",
+            "-\t-\t-",
+        ] {
+            assert_eq!(list_continuation(rule, rule.len()), None, "{rule:?}");
+        }
+        assert_eq!(list_continuation("- - item", 8), item("- "));
+        assert_eq!(list_continuation("* -- *", 6), item("* "));
+    }
+
+    fn spans(text: &str) -> Vec<(Range<usize>, Token)> {
+        highlight_line(text, LineState::default()).0
+    }
+
+    #[test]
+    fn highlights_headings_and_inline_syntax() {
+        assert_eq!(spans("## Title"), [(0..8, Token::Heading)]);
+        assert_eq!(spans("#hashtag"), []);
+        // Indented four spaces, this is a code block.
+        assert_eq!(spans("    # not a heading"), []);
+        assert_eq!(spans("   # Title"), [(0..10, Token::Heading)]);
+        assert_eq!(
+            spans("*a* **b** `c`"),
+            [
+                (0..3, Token::Emphasis),
+                (4..9, Token::Strong),
+                (10..13, Token::Code)
+            ]
+        );
+        assert_eq!(
+            spans("[docs](https://x.y)"),
+            [(0..6, Token::Link), (6..19, Token::LinkUrl)]
+        );
+    }
+
+    #[test]
+    fn highlights_list_markers_and_rules() {
+        assert_eq!(spans("  - item"), [(2..3, Token::ListMarker)]);
+        assert_eq!(spans("12) item"), [(0..3, Token::ListMarker)]);
+        assert_eq!(spans("* * *"), [(0..5, Token::Rule)]);
+        assert_eq!(spans(" - - -"), [(1..6, Token::Rule)]);
+        assert_eq!(
+            spans("> ***"),
+            [(0..5, Token::BlockQuote), (2..5, Token::Rule)]
+        );
+    }
+
+    #[test]
+    fn fences_carry_over_to_the_following_lines() {
+        let (fence, state) = highlight_line("```rust", LineState::default());
+        assert_eq!(fence, [(0..7, Token::CodeFence)]);
+        let (code, state) = highlight_line("# not a heading", state);
+        assert_eq!(code, [(0..15, Token::CodeBlock)]);
+        // A shorter fence does not close the block.
+        let (code, state) = highlight_line("``", state);
+        assert_eq!(code, [(0..2, Token::CodeBlock)]);
+        let (close, state) = highlight_line("```", state);
+        assert_eq!(close, [(0..3, Token::CodeFence)]);
+        assert_eq!(state, LineState::default());
+    }
+
+    #[test]
+    fn highlighter_rehighlights_lines_after_a_new_fence() {
+        let lines = ["# a", "b"];
+        let mut highlighter = MarkdownHighlighter::new(2);
+        highlighter.sync(|line| lines[line].to_owned());
+        assert_eq!(highlighter.spans(0), [(0..3, Token::Heading)]);
+        assert_eq!(highlighter.spans(1), []);
+
+        let lines = ["~~~", "# a", "b"];
+        highlighter.edit(0..0, 1);
+        highlighter.sync(|line| lines[line].to_owned());
+        assert_eq!(highlighter.spans(1), [(0..3, Token::CodeBlock)]);
+        assert_eq!(highlighter.spans(2), [(0..1, Token::CodeBlock)]);
+    }
+}
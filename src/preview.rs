@@ -0,0 +1,464 @@
+use crate::export::OPTIONS;
+use crate::markdown::Token;
+use crate::text_element::text_runs;
+use crate::text_input::{TextInput, TextInputEvent};
+use gpui::*;
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use std::ops::Range;
+
+/// A run of formatted text, such as a paragraph or a table cell.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Inline {
+    pub text: String,
+    /// Byte ranges of `text` and their markdown styling, innermost first.
+    pub highlights: Vec<(Range<usize>, Token)>,
+}
+
+/// A block of a parsed markdown document.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Block {
+    Heading(u8, Inline),
+    Paragraph(Inline),
+    /// A bulleted list, or a numbered one counting from `start`.
+    List {
+        start: Option<u64>,
+        items: Vec<Vec<Block>>,
+    },
+    CodeBlock(String),
+    Quote(Vec<Block>),
+    Table {
+        head: Vec<Inline>,
+        rows: Vec<Vec<Inline>>,
+    },
+    Rule,
+}
+
+/// Parses `text` into its top-level blocks, each with the line it starts on.
+pub fn parse(text: &str) -> Vec<(usize, Block)> {
+    let mut events = Parser::new_ext(text, OPTIONS).into_offset_iter();
+    let mut line = 0;
+    let mut offset = 0;
+    blocks(&mut events)
+        .into_iter()
+        .map(|(start, block)| {
+            line += text[offset..start].matches('\n').count();
+            offset = start;
+            (line, block)
+        })
+        .collect()
+}
+
+/// Reads blocks up to the end of the containing block, each with the
+/// offset it starts at.
+fn blocks<'a>(events: &mut impl Iterator<Item = (Event<'a>, Range<usize>)>) -> Vec<(usize, Block)> {
+    let mut blocks = Vec::new();
+    // The items of tight lists hold text without a paragraph around it.
+    let mut loose_text: Option<(usize, InlineBuilder)> = None;
+    while let Some((event, range)) = events.next() {
+        if loose_text
+            .get_or_insert_with(|| (range.start, InlineBuilder::default()))
+            .1
+            .push(&event)
+        {
+            continue;
+        }
+        let block = match event {
+            Event::End(_) => break,
+            Event::Start(Tag::Paragraph) => Block::Paragraph(inline(events)),
+            Event::Start(Tag::Heading { level, .. }) => Block::Heading(level as u8, inline(events)),
+            Event::Start(Tag::BlockQuote(_)) => Block::Quote(without_offsets(self::blocks(events))),
+            Event::Start(Tag::CodeBlock(_)) => Block::CodeBlock(code(events)),
+            Event::Start(Tag::List(start)) => Block::List {
+                start,
+                items: list_items(events),
+            },
+            Event::Start(Tag::Table(_)) => table(events),
+            Event::Rule => Block::Rule,
+            // Other containers, such as footnote definitions, are shown as
+            // their contents.
+            Event::Start(_) => {
+                push_loose_text(&mut blocks, loose_text.take());
+                blocks.extend(self::blocks(events));
+                continue;
+            }
+            _ => continue,
+        };
+        push_loose_text(&mut blocks, loose_text.take());
+        blocks.push((range.start, block));
+    }
+    push_loose_text(&mut blocks, loose_text);
+    blocks
+}
+
+fn push_loose_text(blocks: &mut Vec<(usize, Block)>, text: Option<(usize, InlineBuilder)>) {
+    if let Some((start, text)) = text.filter(|(_, text)| !text.is_empty()) {
+        blocks.push((start, Block::Paragraph(text.inline)));
+    }
+}
+
+fn without_offsets(blocks: Vec<(usize, Block)>) -> Vec<Block> {
+    blocks.into_iter().map(|(_, block)| block).collect()
+}
+
+/// Reads the text of a paragraph, heading or table cell.
+fn inline<'a>(events: &mut impl Iterator<Item = (Event<'a>, Range<usize>)>) -> Inline {
+    let mut text = InlineBuilder::default();
+    for (event, _) in events {
+        if !text.push(&event) && matches!(event, Event::End(_)) {
+            break;
+        }
+    }
+    text.inline
+}
+
+fn code<'a>(events: &mut impl Iterator<Item = (Event<'a>, Range<usize>)>) -> String {
+    let mut code = String::new();
+    for (event, _) in events {
+        match event {
+            Event::Text(text) => code.push_str(&text),
+            Event::End(_) => break,
+            _ => {}
+        }
+    }
+    code.truncate(code.trim_end_matches('\n').len());
+    code
+}
+
+fn list_items<'a>(events: &mut impl Iterator<Item = (Event<'a>, Range<usize>)>) -> Vec<Vec<Block>> {
+    let mut items = Vec::new();
+    while let Some((event, _)) = events.next() {
+        match event {
+            Event::Start(Tag::Item) => items.push(without_offsets(blocks(events))),
+            Event::End(_) => break,
+            _ => {}
+        }
+    }
+    items
+}
+
+fn table<'a>(events: &mut impl Iterator<Item = (Event<'a>, Range<usize>)>) -> Block {
+    let mut head = Vec::new();
+    let mut rows = Vec::new();
+    while let Some((event, _)) = events.next() {
+        match event {
+            Event::Start(Tag::TableHead) => head = cells(events),
+            Event::Start(Tag::TableRow) => rows.push(cells(events)),
+            Event::End(_) => break,
+            _ => {}
+        }
+    }
+    Block::Table { head, rows }
+}
+
+fn cells<'a>(events: &mut impl Iterator<Item = (Event<'a>, Range<usize>)>) -> Vec<Inline> {
+    let mut cells = Vec::new();
+    while let Some((event, _)) = events.next() {
+        match event {
+            Event::Start(Tag::TableCell) => cells.push(inline(events)),
+            Event::End(_) => break,
+            _ => {}
+        }
+    }
+    cells
+}
+
+/// Collects inline events into an `Inline`, styling the text between the
+/// start and end of emphasis, strong emphasis and links.
+#[derive(Default)]
+struct InlineBuilder {
+    inline: Inline,
+    open: Vec<(Token, usize)>,
+}
+
+impl InlineBuilder {
+    fn is_empty(&self) -> bool {
+        self.inline.text.trim().is_empty()
+    }
+
+    /// Adds `event` if it is inline, and returns whether it was.
+    fn push(&mut self, event: &Event) -> bool {
+        let text = &mut self.inline.text;
+        match event {
+            Event::Text(content) | Event::Html(content) | Event::InlineHtml(content) => {
+                text.push_str(content)
+            }
+            Event::Code(code) => {
+                let start = text.len();
+                text.push_str(code);
+                self.inline
+                    .highlights
+                    .push((start..text.len(), Token::Code));
+            }
+            Event::SoftBreak => text.push(' '),
+            Event::HardBreak => text.push('\n'),
+            Event::TaskListMarker(done) => text.push_str(if *done { "☑ " } else { "☐ " }),
+            Event::Start(Tag::Emphasis) => self.open.push((Token::Emphasis, text.len())),
+            Event::Start(Tag::Strong) => self.open.push((Token::Strong, text.len())),
+            Event::Start(Tag::Link { .. } | Tag::Image { .. }) => {
+                self.open.push((Token::Link, text.len()))
+            }
+            Event::End(TagEnd::Emphasis | TagEnd::Strong | TagEnd::Link | TagEnd::Image) => {
+                if let Some((token, start)) = self.open.pop() {
+                    self.inline.highlights.push((start..text.len(), token));
+                }
+            }
+            Event::Start(Tag::Strikethrough) | Event::End(TagEnd::Strikethrough) => {}
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// The document of a `TextInput` rendered as formatted markdown. The
+/// preview is parsed again when the document changes, and scrolls to keep
+/// the block under the editor's cursor in view.
+pub struct MarkdownPreview {
+    pub editor: View<TextInput>,
+    blocks: Vec<(usize, Block)>,
+    /// Editor revision `blocks` were parsed from.
+    revision: Option<usize>,
+    scroll_handle: ScrollHandle,
+    _subscriptions: Vec<Subscription>,
+}
+
+impl MarkdownPreview {
+    pub fn new(editor: View<TextInput>, cx: &mut ViewContext<Self>) -> Self {
+        let _subscriptions = vec![
+            cx.observe(&editor, |_, _, cx| cx.notify()),
+            cx.subscribe(&editor, |this, _, event, cx| {
+                if let TextInputEvent::SelectionChanged = event {
+                    this.scroll_to_cursor(cx);
+                }
+            }),
+        ];
+        Self {
+            editor,
+            blocks: Vec::new(),
+            revision: None,
+            scroll_handle: ScrollHandle::new(),
+            _subscriptions,
+        }
+    }
+
+    /// Parses the document again if it changed since it was last parsed.
+    fn sync(&mut self, cx: &AppContext) {
+        let editor = self.editor.read(cx);
+        if self.revision != Some(editor.revision()) {
+            self.revision = Some(editor.revision());
+            self.blocks = parse(&editor.document.buffer.to_string());
+        }
+    }
+
+    /// Scrolls to the block the cursor of the editor is in.
+    fn scroll_to_cursor(&mut self, cx: &mut ViewContext<Self>) {
+        self.sync(cx);
+        let line = self.editor.read(cx).state.selection().head.line;
+        let ix = self
+            .blocks
+            .partition_point(|(start, _)| *start <= line)
+            .saturating_sub(1);
+        self.scroll_handle.scroll_to_item(ix);
+        cx.notify();
+    }
+}
+
+impl Render for MarkdownPreview {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        self.sync(cx);
+        let style = cx.text_style();
+        div()
+            .id("markdown-preview")
+            .track_scroll(&self.scroll_handle)
+            .overflow_y_scroll()
+            .size_full()
+            .p(px(8.))
+            .flex()
+            .flex_col()
+            .gap(px(8.))
+            .text_size(px(14.))
+            .children(
+                self.blocks
+                    .iter()
+                    .map(|(_, block)| render_block(block, &style)),
+            )
+    }
+}
+
+fn render_blocks(blocks: &[Block], style: &TextStyle) -> Div {
+    div()
+        .flex()
+        .flex_col()
+        .gap(px(4.))
+        .children(blocks.iter().map(|block| render_block(block, style)))
+}
+
+fn render_block(block: &Block, style: &TextStyle) -> AnyElement {
+    match block {
+        Block::Heading(level, text) => {
+            let size = match level {
+                1 => 26.,
+                2 => 21.,
+                3 => 17.,
+                _ => 15.,
+            };
+            div()
+                .text_size(px(size))
+                .child(render_inline(text, style, Some(Token::Heading)))
+                .into_any_element()
+        }
+        Block::Paragraph(text) => div()
+            .child(render_inline(text, style, None))
+            .into_any_element(),
+        Block::List { start, items } => div()
+            .flex()
+            .flex_col()
+            .gap(px(2.))
+            .children(items.iter().zip(0..).map(|(item, ix)| {
+                let marker = match start {
+                    Some(start) => format!("{}.", start + ix),
+                    None => "•".to_owned(),
+                };
+                div()
+                    .flex()
+                    .flex_row()
+                    .gap(px(6.))
+                    .child(div().flex_none().child(marker))
+                    .child(render_blocks(item, style).flex_1())
+            }))
+            .into_any_element(),
+        Block::CodeBlock(code) => div()
+            .p(px(8.))
+            .rounded(px(4.))
+            .bg(rgb(0xf6f8fa))
+            .child(code.clone())
+            .into_any_element(),
+        Block::Quote(blocks) => {
+            let style = TextStyle {
+                color: rgb(0x6a737d).into(),
+                ..style.clone()
+            };
+            render_blocks(blocks, &style)
+                .pl(px(10.))
+                .border_l_4()
+                .border_color(rgb(0xdddddd))
+                .into_any_element()
+        }
+        Block::Table { head, rows } => {
+            let row = |cells: &[Inline], header: bool| {
+                div().flex().flex_row().children(cells.iter().map(|cell| {
+                    div()
+                        .flex_1()
+                        .px(px(6.))
+                        .py(px(2.))
+                        .border_1()
+                        .border_color(rgb(0xdddddd))
+                        .when(header, |cell| cell.bg(rgb(0xf6f8fa)))
+                        .child(render_inline(cell, style, header.then_some(Token::Strong)))
+                }))
+            };
+            div()
+                .flex()
+                .flex_col()
+                .child(row(head, true))
+                .children(rows.iter().map(|cells| row(cells, false)))
+                .into_any_element()
+        }
+        Block::Rule => div().h(px(1.)).bg(rgb(0xdddddd)).into_any_element(),
+    }
+}
+
+/// `text` with its highlights, on top of `base` styling for all of it.
+fn render_inline(text: &Inline, style: &TextStyle, base: Option<Token>) -> StyledText {
+    let highlights = base
+        .map(|token| (0..text.text.len(), token))
+        .into_iter()
+        .chain(text.highlights.iter().cloned())
+        .map(|(range, token)| (range, token.style()))
+        .collect::<Vec<_>>();
+    StyledText::new(text.text.clone()).with_runs(text_runs(style, text.text.len(), &highlights))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paragraph(text: &str, highlights: Vec<(Range<usize>, Token)>) -> Block {
+        Block::Paragraph(Inline {
+            text: text.to_owned(),
+            highlights,
+        })
+    }
+
+    #[test]
+    fn blocks_start_on_their_source_lines() {
+        let text = "# Title\n\nSome *text*\nmore\n\n---\n\n```\ncode\n```\n";
+        assert_eq!(
+            parse(text),
+            [
+                (
+                    0,
+                    Block::Heading(
+                        1,
+                        Inline {
+                            text: "Title".to_owned(),
+                            highlights: Vec::new(),
+                        }
+                    )
+                ),
+                (
+                    2,
+                    paragraph("Some text more", vec![(5..9, Token::Emphasis)])
+                ),
+                (5, Block::Rule),
+                (7, Block::CodeBlock("code".to_owned())),
+            ]
+        );
+    }
+
+    #[test]
+    fn lists_hold_their_items() {
+        assert_eq!(
+            parse("1. a\n2. **b**\n\n- [x] done\n"),
+            [
+                (
+                    0,
+                    Block::List {
+                        start: Some(1),
+                        items: vec![
+                            vec![paragraph("a", Vec::new())],
+                            vec![paragraph("b", vec![(0..1, Token::Strong)])],
+                        ],
+                    }
+                ),
+                (
+                    3,
+                    Block::List {
+                        start: None,
+                        items: vec![vec![paragraph("☑ done", Vec::new())]],
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_quotes_and_tables() {
+        let cell = |text: &str| Inline {
+            text: text.to_owned(),
+            highlights: Vec::new(),
+        };
+        assert_eq!(
+            parse("> quoted\n> text\n\n| a | b |\n|---|---|\n| 1 | 2 |\n"),
+            [
+                (0, Block::Quote(vec![paragraph("quoted text", Vec::new())])),
+                (
+                    3,
+                    Block::Table {
+                        head: vec![cell("a"), cell("b")],
+                        rows: vec![vec![cell("1"), cell("2")]],
+                    }
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,348 @@
+use regex::{Regex, RegexBuilder};
+use std::ops::Range;
+
+/// Options the find bar offers on top of the query text.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchOptions {
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    /// Treat the query as a regular expression rather than literal text.
+    pub regex: bool,
+}
+
+/// A compiled search over the whole document. Matches are byte ranges of the
+/// document text, so they may span several lines.
+#[derive(Clone, Debug)]
+pub struct SearchQuery {
+    text: String,
+    options: SearchOptions,
+    regex: Regex,
+}
+
+impl SearchQuery {
+    /// Compiles `text` with `options`. Returns `None` for an empty query,
+    /// which matches nothing, and for an invalid regular expression.
+    pub fn new(text: &str, options: SearchOptions) -> Option<Self> {
+        if text.is_empty() {
+            return None;
+        }
+        let mut pattern = if options.regex {
+            text.to_owned()
+        } else {
+            regex::escape(text)
+        };
+        if options.whole_word {
+            pattern = format!(r"\b(?:{pattern})\b");
+        }
+        let regex = RegexBuilder::new(&pattern)
+            .case_insensitive(!options.case_sensitive)
+            .multi_line(true)
+            .build()
+            .ok()?;
+        Some(Self {
+            text: text.to_owned(),
+            options,
+            regex,
+        })
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn options(&self) -> SearchOptions {
+        self.options
+    }
+
+    /// Every non-overlapping match in `text`, in order.
+    pub fn find_all(&self, text: &str) -> Vec<Range<usize>> {
+        self.find_from(text, 0).collect()
+    }
+
+    /// The matches in `text` from `start` on, as a search of the whole text
+    /// finds them once it gets to `start`. Empty matches are skipped.
+    fn find_from<'a>(
+        &'a self,
+        text: &'a str,
+        start: usize,
+    ) -> impl Iterator<Item = Range<usize>> + 'a {
+        let mut next = Some(start);
+        std::iter::from_fn(move || loop {
+            let found = self.regex.find_at(text, next?)?.range();
+            if !found.is_empty() {
+                next = Some(found.end);
+                return Some(found);
+            }
+            next = text[found.end..]
+                .chars()
+                .next()
+                .map(|ch| found.end + ch.len_utf8());
+        })
+    }
+
+    /// Whether a match may contain a line break, so that an edit can change
+    /// matches that start on earlier lines.
+    fn can_span_lines(&self) -> bool {
+        self.options.regex || self.text.contains('\n')
+    }
+
+    /// The text to put in place of the match at `range` of `text`. In regex
+    /// mode `$1` or `${name}` in `replacement` expand to the capture groups of
+    /// the match; otherwise `replacement` is used as is.
+    pub fn expand(&self, text: &str, range: Range<usize>, replacement: &str) -> String {
+        if !self.options.regex {
+            return replacement.to_owned();
+        }
+        let mut expanded = String::new();
+        if let Some(captures) = self.regex.captures_at(text, range.start) {
+            captures.expand(replacement, &mut expanded);
+        }
+        expanded
+    }
+
+    /// Every match in `text` paired with the text that replaces it.
+    pub fn replace_all(&self, text: &str, replacement: &str) -> Vec<(Range<usize>, String)> {
+        self.regex
+            .captures_iter(text)
+            .filter_map(|captures| {
+                let range = captures.get(0)?.range();
+                if range.is_empty() {
+                    return None;
+                }
+                let mut expanded = String::new();
+                if self.options.regex {
+                    captures.expand(replacement, &mut expanded);
+                } else {
+                    expanded.push_str(replacement);
+                }
+                Some((range, expanded))
+            })
+            .collect()
+    }
+
+    /// Every match in `text` replaced at once, as a single edit of the range
+    /// from the first match to the last, so that it is undone in one step.
+    /// Returns `None` when nothing matches.
+    pub fn replace_all_edit(
+        &self,
+        text: &str,
+        replacement: &str,
+    ) -> Option<(Range<usize>, String)> {
+        let replacements = self.replace_all(text, replacement);
+        let span = replacements.first()?.0.start..replacements.last()?.0.end;
+        let mut new_text = String::new();
+        let mut offset = span.start;
+        for (range, replacement) in &replacements {
+            new_text.push_str(&text[offset..range.start]);
+            new_text.push_str(replacement);
+            offset = range.end;
+        }
+        new_text.push_str(&text[offset..span.end]);
+        Some((span, new_text))
+    }
+}
+
+/// The matches of a search in a document, kept up to date across edits.
+/// An edit only shifts the matches after it and marks the text around it
+/// stale, and `sync` searches again from just before the stale text until
+/// the search finds the matches it found before.
+#[derive(Clone, Debug, Default)]
+pub struct Matches {
+    ranges: Vec<Range<usize>>,
+    /// Byte range of the document edited since the last `sync`.
+    stale: Option<Range<usize>>,
+}
+
+impl Matches {
+    /// Every match of `query` in `text`, or none without a query.
+    pub fn new(query: Option<&SearchQuery>, text: &str) -> Self {
+        Self {
+            ranges: query.map_or_else(Vec::new, |query| query.find_all(text)),
+            stale: None,
+        }
+    }
+
+    /// The matches in order, as of the last `sync`.
+    pub fn as_slice(&self) -> &[Range<usize>] {
+        &self.ranges
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.stale.is_some()
+    }
+
+    /// Records that `range` of the document was replaced by `new_len`
+    /// bytes. Matches touching `range` are dropped until the next `sync`.
+    pub fn edit(&mut self, range: Range<usize>, new_len: usize) {
+        let new_end = range.start + new_len;
+        let map = |offset: usize| {
+            if offset <= range.start {
+                offset
+            } else if offset >= range.end {
+                offset - range.end + new_end
+            } else {
+                new_end
+            }
+        };
+        let first = self.ranges.partition_point(|m| m.end < range.start);
+        let last = self.ranges.partition_point(|m| m.start <= range.end);
+        let mut stale = range.start..new_end;
+        if let Some(old) = self.stale.take() {
+            stale = stale.start.min(map(old.start))..stale.end.max(map(old.end));
+        }
+        if first < last {
+            stale.start = stale.start.min(self.ranges[first].start);
+            stale.end = stale.end.max(map(self.ranges[last - 1].end));
+        }
+        self.ranges.drain(first..last);
+        for m in &mut self.ranges[first..] {
+            *m = map(m.start)..map(m.end);
+        }
+        self.stale = Some(stale);
+    }
+
+    /// Searches the edited part of `text` again.
+    pub fn sync(&mut self, query: &SearchQuery, text: &str) {
+        let Some(stale) = self.stale.take() else {
+            return;
+        };
+        // A search of the whole text would get to the stale text from the
+        // end of the match before it. Matches that can't span lines can't
+        // start before the line either.
+        let before = self.ranges.partition_point(|m| m.end < stale.start);
+        let mut start = self.ranges[..before].last().map_or(0, |m| m.end);
+        if !query.can_span_lines() {
+            start = start.max(text[..stale.start].rfind('\n').map_or(0, |ix| ix + 1));
+        }
+
+        let first = self.ranges.partition_point(|m| m.end <= start);
+        let mut found = Vec::new();
+        for range in query.find_from(text, start) {
+            if range.start >= stale.end {
+                // From a match it found before, the search goes on as
+                // before, since the text after it is unchanged.
+                let ix = self.ranges.partition_point(|m| m.start < range.start);
+                if self.ranges.get(ix) == Some(&range) {
+                    self.ranges.splice(first..ix, found);
+                    return;
+                }
+            }
+            found.push(range);
+        }
+        self.ranges.splice(first.., found);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regex(pattern: &str) -> SearchQuery {
+        let options = SearchOptions {
+            regex: true,
+            ..Default::default()
+        };
+        SearchQuery::new(pattern, options).unwrap()
+    }
+
+    fn apply(text: &str, (range, new_text): (Range<usize>, String)) -> String {
+        let mut text = text.to_owned();
+        text.replace_range(range, &new_text);
+        text
+    }
+
+    #[test]
+    fn replace_all_matches_across_lines() {
+        let text = "one\n\n\ntwo\n\nthree";
+        let query = SearchQuery::new("\n\n", SearchOptions::default()).unwrap();
+        assert_eq!(
+            query.replace_all(text, " "),
+            [(3..5, " ".to_owned()), (9..11, " ".to_owned())]
+        );
+        let query = regex(r"\n\n");
+        assert_eq!(query.find_all(text), [3..5, 9..11]);
+        assert_eq!(
+            apply(text, query.replace_all_edit(text, "\n").unwrap()),
+            "one\n\ntwo\nthree"
+        );
+    }
+
+    #[test]
+    fn expand_uses_capture_groups_in_regex_mode() {
+        let text = "let x = 1;";
+        let query = regex(r"(\w+) = (\d+)");
+        assert_eq!(query.expand(text, 4..9, "$2 = $1"), "1 = x");
+        assert_eq!(query.replace_all(text, "${2}0"), [(4..9, "10".to_owned())]);
+
+        let query = SearchQuery::new("x", SearchOptions::default()).unwrap();
+        assert_eq!(query.expand(text, 4..5, "$1"), "$1");
+    }
+
+    #[test]
+    fn replace_all_is_a_single_edit() {
+        let text = "a1 b2 c3";
+        let query = regex(r"([a-z])(\d)");
+        let (range, new_text) = query.replace_all_edit(text, "$2$1").unwrap();
+        assert_eq!(range, 0..8);
+        assert_eq!(new_text, "1a 2b 3c");
+        assert_eq!(query.replace_all_edit("none", ""), None);
+    }
+
+    /// Applies `edits` to `text` one at a time, checking after each that
+    /// the synced matches are those of a full search.
+    fn check_edits(query: &SearchQuery, text: &str, edits: &[(Range<usize>, &str)]) {
+        let mut text = text.to_owned();
+        let mut matches = Matches::new(Some(query), &text);
+        for (range, new_text) in edits {
+            text.replace_range(range.clone(), new_text);
+            matches.edit(range.clone(), new_text.len());
+            matches.sync(query, &text);
+            assert_eq!(matches.as_slice(), query.find_all(&text), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn matches_follow_edits() {
+        let query = SearchQuery::new("ab", SearchOptions::default()).unwrap();
+        let text = "ab x ab\nab\nxx ab";
+        check_edits(
+            &query,
+            text,
+            &[
+                (0..0, "a"),
+                (1..3, ""),
+                (5..5, "b"),
+                (8..9, "a"),
+                (0..14, "ab"),
+            ],
+        );
+    }
+
+    #[test]
+    fn matches_follow_edits_in_regex_mode() {
+        let query = regex(r"^\w+$|a\nb");
+        let text = "one\ntwo three\na\nc\nfour";
+        check_edits(
+            &query,
+            text,
+            &[(7..8, ""), (17..18, "b"), (15..15, "x"), (0..3, "a\nb")],
+        );
+    }
+
+    #[test]
+    fn matches_are_synced_once_for_several_edits() {
+        let query = SearchQuery::new("x", SearchOptions::default()).unwrap();
+        let mut text = "x.x.x.x".to_owned();
+        let mut matches = Matches::new(Some(&query), &text);
+        for offset in [6, 4, 0] {
+            text.replace_range(offset..offset + 1, "yy");
+            matches.edit(offset..offset + 1, 2);
+        }
+        // The untouched match has only moved.
+        assert_eq!(matches.as_slice().len(), 1);
+        assert_eq!(matches.as_slice()[0], 3..4);
+        assert!(matches.is_stale());
+        matches.sync(&query, &text);
+        assert_eq!(matches.as_slice(), query.find_all(&text));
+    }
+}
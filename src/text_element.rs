@@ -1,6 +1,11 @@
+use crate::buffer::TextPosition;
+use crate::display_map::{DisplayRow, TabMap};
 use crate::text_input::TextInput;
 use gpui::*;
+use std::ops::Range;
 
+/// One display row of a `TextInput`: a whole line, or the part of a soft
+/// wrapped line that fits on a single row.
 pub struct TextElement {
     pub input: View<TextInput>,
     pub index: usize,
@@ -14,10 +19,37 @@ impl IntoElement for TextElement {
     }
 }
 
+/// A shaped display row. Tabs are expanded to spaces before shaping, so
+/// columns of the row go through `tabs` to index into `line`.
+pub struct RowLayout {
+    pub line: ShapedLine,
+    pub tabs: TabMap,
+}
+
+impl RowLayout {
+    pub fn x_for_column(&self, column: usize) -> Pixels {
+        self.line.x_for_index(self.tabs.to_expanded(column))
+    }
+
+    pub fn closest_column_for_x(&self, x: Pixels) -> usize {
+        self.tabs.to_column(self.line.closest_index_for_x(x))
+    }
+}
+
+/// Where a row was painted, what it showed and how it was shaped, kept for
+/// hit testing and IME positioning until the next frame. The row is kept
+/// too, since the display map may be rewrapped before then.
+pub struct PaintedLine {
+    pub bounds: Bounds<Pixels>,
+    pub row: DisplayRow,
+    pub layout: RowLayout,
+}
+
 pub struct PrepaintState {
-    line: Option<ShapedLine>,
-    cursor: Option<PaintQuad>,
-    selection: Option<PaintQuad>,
+    row: Option<DisplayRow>,
+    layout: Option<RowLayout>,
+    cursors: Vec<PaintQuad>,
+    selections: Vec<PaintQuad>,
 }
 
 impl Element for TextElement {
@@ -47,91 +79,122 @@ impl Element for TextElement {
         _request_layout: &mut Self::RequestLayoutState,
         cx: &mut WindowContext,
     ) -> Self::PrepaintState {
-        let input = self.input.read(cx);
-        let content = input.content[self.index].content.clone();
-        let selected_range = input.content[self.index].selected_range.clone();
-        let cursor = input.cursor_offset();
         let style = cx.text_style();
+        self.input.update(cx, |input, cx| {
+            input.set_row_style(&style, bounds.size.width, cx)
+        });
+        let input = self.input.read(cx);
+        let row = input.display_map.row(self.index);
+        let content = input.line(row.line).content;
+        let selections = input.state.selections.as_slice();
+        let line_start = input.document.buffer.line_start(row.line) + row.columns.start;
 
-        let (display_text, text_color) = (content.clone(), style.color);
-
-        let run = TextRun {
-            len: display_text.len(),
-            font: style.font(),
-            color: text_color,
-            background_color: None,
-            underline: None,
-            strikethrough: None,
+        let row_text = &content[row.columns.clone()];
+        let (display_text, tabs) = TabMap::expand(row_text, input.tab_width);
+        let line_end = line_start + row_text.len();
+        // Document ranges that touch this row, as ranges of the expanded
+        // row text.
+        let to_row = |range: &Range<usize>| {
+            (range.start <= line_end && range.end >= line_start).then(|| {
+                tabs.to_expanded(range.start.max(line_start) - line_start)
+                    ..tabs.to_expanded(range.end.min(line_end) - line_start)
+            })
         };
-        let runs = if let Some(marked_range) = input.content[self.index].marked_range.as_ref() {
-            vec![
-                TextRun {
-                    len: marked_range.start,
-                    ..run.clone()
-                },
-                TextRun {
-                    len: marked_range.end - marked_range.start,
-                    underline: Some(UnderlineStyle {
-                        color: Some(run.color),
-                        thickness: px(1.0),
-                        wavy: false,
-                    }),
-                    ..run.clone()
-                },
-                TextRun {
-                    len: display_text.len() - marked_range.end,
-                    ..run.clone()
+        let mut highlights = input.syntax_highlights(&row, &tabs);
+        let first_match = input
+            .matches
+            .as_slice()
+            .partition_point(|range| range.end <= line_start);
+        let active_match = input.active_match();
+        highlights.extend(
+            input.matches.as_slice()[first_match..]
+                .iter()
+                .zip(first_match..)
+                .take_while(|(range, _)| range.start < line_end)
+                .filter_map(|(range, ix)| {
+                    let color = if Some(ix) == active_match {
+                        rgba(0xFF9900AA)
+                    } else {
+                        rgba(0xFFE0664D)
+                    };
+                    let highlight = HighlightStyle {
+                        background_color: Some(color.into()),
+                        ..Default::default()
+                    };
+                    Some((to_row(range)?, highlight))
+                }),
+        );
+        // The IME composition is underlined.
+        if let Some(marked_range) = input.state.marked_range.as_ref().and_then(to_row) {
+            let underline = UnderlineStyle {
+                color: Some(style.color),
+                thickness: px(1.0),
+                wavy: false,
+            };
+            highlights.push((
+                marked_range,
+                HighlightStyle {
+                    underline: Some(underline),
+                    ..Default::default()
                 },
-            ]
-            .into_iter()
-            .filter(|run| run.len > 0)
-            .collect()
-        } else {
-            vec![run]
-        };
+            ));
+        }
+        let runs = text_runs(&style, display_text.len(), &highlights);
 
         let font_size = style.font_size.to_pixels(cx.rem_size());
-        let line = cx
-            .text_system()
-            .shape_line(display_text, font_size, &runs)
-            .unwrap();
+        let line = RowLayout {
+            line: cx
+                .text_system()
+                .shape_line(display_text.into(), font_size, &runs)
+                .unwrap(),
+            tabs,
+        };
 
-        let cursor_pos = line.x_for_index(cursor);
-        let (selection, cursor) = if selected_range.is_empty() && self.index == input.content_idx {
-            (
-                None,
-                Some(fill(
-                    Bounds::new(
-                        point(bounds.left() + cursor_pos, bounds.top()),
-                        size(px(2.), bounds.bottom() - bounds.top()),
-                    ),
-                    gpui::blue(),
-                )),
-            )
-        } else if self.index == input.content_idx {
-            (
-                Some(fill(
-                    Bounds::from_corners(
-                        point(
-                            bounds.left() + line.x_for_index(selected_range.start),
-                            bounds.top(),
-                        ),
-                        point(
-                            bounds.left() + line.x_for_index(selected_range.end),
-                            bounds.bottom(),
+        // Columns are relative to the line, so shift them onto this row.
+        let row_column = |position: TextPosition| {
+            position.column.clamp(row.columns.start, row.columns.end) - row.columns.start
+        };
+        let row_start = TextPosition::new(row.line, row.columns.start);
+        let row_end = TextPosition::new(row.line, row.columns.end);
+        let first = selections.partition_point(|selection| selection.end() < row_start);
+        let mut cursors = Vec::new();
+        let mut selection_quads = Vec::new();
+        for selection in selections[first..]
+            .iter()
+            .take_while(|selection| selection.start() <= row_end)
+        {
+            let (start, end) = (selection.start(), selection.end());
+            if selection.is_empty() {
+                if input.display_map.row_for_position(selection.head) == self.index {
+                    let cursor_pos = line.x_for_column(row_column(selection.head));
+                    cursors.push(fill(
+                        Bounds::new(
+                            point(bounds.left() + cursor_pos, bounds.top()),
+                            size(px(2.), bounds.bottom() - bounds.top()),
                         ),
-                    ),
+                        gpui::blue(),
+                    ));
+                }
+            } else {
+                // Lines the selection continues past are filled up to the
+                // right edge so that the selected newline is visible.
+                let left = bounds.left() + line.x_for_column(row_column(start));
+                let right = if end.line > row.line && row.is_line_end {
+                    bounds.right()
+                } else {
+                    bounds.left() + line.x_for_column(row_column(end))
+                };
+                selection_quads.push(fill(
+                    Bounds::from_corners(point(left, bounds.top()), point(right, bounds.bottom())),
                     rgba(0x3311FF30),
-                )),
-                None,
-            )
-        } else {
-            (None, None)
-        };
+                ));
+            }
+        }
         PrepaintState {
-            line: Some(line),
-            cursor,
-            selection,
+            row: Some(row),
+            layout: Some(line),
+            cursors,
+            selections: selection_quads,
         }
     }
 
@@ -148,22 +211,67 @@ impl Element for TextElement {
             &focus_handle,
             ElementInputHandler::new(bounds, self.input.clone()),
         );
-        if let Some(selection) = prepaint.selection.take() {
+        for selection in prepaint.selections.drain(..) {
             cx.paint_quad(selection)
         }
-        let line = prepaint.line.take().unwrap();
-        line.paint(bounds.origin, cx.line_height(), cx).unwrap();
+        let row = prepaint.row.take().unwrap();
+        let layout = prepaint.layout.take().unwrap();
+        layout
+            .line
+            .paint(bounds.origin, cx.line_height(), cx)
+            .unwrap();
 
         if focus_handle.is_focused(cx) {
-            if let Some(cursor) = prepaint.cursor.take() {
+            for cursor in prepaint.cursors.drain(..) {
                 cx.paint_quad(cursor);
             }
         }
 
-        self.input.update(cx, |input, _cx| {
-            input.content[self.index].last_layout = Some(line);
-            input.last_bounds = Some(bounds);
-            input.check_bounds(self.index, _cx);
+        self.input.update(cx, |input, _| {
+            input.last_layouts.insert(
+                self.index,
+                PaintedLine {
+                    bounds,
+                    row,
+                    layout,
+                },
+            );
         });
     }
 }
+
+/// Runs of `len` bytes of text in `style`, split at the edges of
+/// `highlights` so that each part gets the highlights covering it. Later
+/// highlights take precedence over earlier ones.
+pub(crate) fn text_runs(
+    style: &TextStyle,
+    len: usize,
+    highlights: &[(Range<usize>, HighlightStyle)],
+) -> Vec<TextRun> {
+    let mut edges = vec![0, len];
+    edges.extend(
+        highlights
+            .iter()
+            .flat_map(|(range, _)| [range.start, range.end]),
+    );
+    edges.sort_unstable();
+    edges.dedup();
+
+    let runs = edges
+        .windows(2)
+        .map(|edge| {
+            let mut highlight = HighlightStyle::default();
+            for (range, style) in highlights {
+                if range.contains(&edge[0]) {
+                    highlight.highlight(style.clone());
+                }
+            }
+            style.clone().highlight(highlight).to_run(edge[1] - edge[0])
+        })
+        .collect::<Vec<_>>();
+    if runs.is_empty() {
+        vec![style.to_run(len)]
+    } else {
+        runs
+    }
+}
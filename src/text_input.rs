@@ -1,7 +1,20 @@
-use crate::text_element::TextElement;
+use crate::buffer::{Buffer, Document, TextPosition};
+use crate::display_map::{DisplayMap, DisplayRow, TabMap};
+use crate::editor_state::{
+    outdent_len, soft_tab_len, EditorState, SelectMode, Selection, SelectionSet,
+};
+use crate::history::{Edit, History};
+use crate::markdown::{list_continuation, ListContinuation, MarkdownHighlighter, Token};
+use crate::search::{Matches, SearchQuery};
+use crate::text_element::{text_runs, PaintedLine, RowLayout, TextElement};
 use gpui::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
 use std::ops::Range;
-use unicode_segmentation::*;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 actions!(
     text_input,
@@ -12,170 +25,627 @@ actions!(
         Right,
         SelectLeft,
         SelectRight,
+        MoveWordLeft,
+        MoveWordRight,
+        SelectWordLeft,
+        SelectWordRight,
+        DeleteWordBackward,
+        DeleteWordForward,
         SelectAll,
+        SelectNextOccurrence,
+        Cancel,
         Home,
         End,
         ShowCharacterPalette,
         Enter,
+        Tab,
+        Indent,
+        Outdent,
         Up,
-        Down
+        Down,
+        SelectUp,
+        SelectDown,
+        PageUp,
+        PageDown,
+        SelectPageUp,
+        SelectPageDown,
+        SelectToLineStart,
+        SelectToLineEnd,
+        MoveToDocumentStart,
+        MoveToDocumentEnd,
+        SelectToDocumentStart,
+        SelectToDocumentEnd,
+        Undo,
+        Redo,
+        Copy,
+        Cut,
+        Paste,
+        FindNext,
+        FindPrevious
     ]
 );
 
+const TEXT_SIZE: Pixels = Pixels(12.);
+/// Lines wrapped per step of the background wrap, and the pause between
+/// steps that keeps the editor responsive meanwhile.
+const WRAP_CHUNK: usize = 200;
+const WRAP_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Events emitted by `TextInput`, for containers that drive previews,
+/// autosave or status bars off the editor.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TextInputEvent {
+    /// `range` of the buffer, which held `old_text`, now holds `new_text`.
+    /// The range is in byte offsets of the text before the edit.
+    Edited {
+        range: Range<usize>,
+        old_text: String,
+        new_text: String,
+    },
+    SelectionChanged,
+    Focused,
+    Blurred,
+}
+
+/// A read-only view of a single buffer line, handed to `TextElement` for
+/// shaping and painting.
 pub struct TextLine {
     pub content: SharedString,
-    pub selected_range: Range<usize>,
-    pub selection_reversed: bool,
-    pub marked_range: Option<Range<usize>>,
-    pub last_layout: Option<ShapedLine>,
-    pub is_selecting: bool,
 }
 
 pub struct TextInput {
     pub focus_handle: FocusHandle,
-    pub content: Vec<TextLine>,
-    pub content_idx: usize,
-    pub last_bounds: Option<Bounds<Pixels>>,
+    /// The text being edited and the line ending it is saved with.
+    pub document: Document,
+    pub state: EditorState,
+    pub(crate) history: History<SelectionSet>,
+    pub(crate) last_layouts: HashMap<usize, PaintedLine>,
+    pub(crate) scroll_handle: UniformListScrollHandle,
+    pub(crate) display_map: DisplayMap,
+    /// Width rows are wrapped to, taken from the last painted row.
+    pub(crate) wrap_width: Option<Pixels>,
+    /// Style rows were last laid out in, so that actions and mouse handlers
+    /// shape and wrap text the way it is painted.
+    pub(crate) text_style: Option<TextStyle>,
+    /// Wraps the lines that are not on screen, a chunk at a time.
+    wrap_task: Option<Task<()>>,
+    /// Columns between tab stops, used both to render tabs and for the
+    /// spaces inserted by `Tab` and `Indent`.
+    pub(crate) tab_width: usize,
+    /// Whether `Tab` and `Indent` insert a tab character rather than spaces.
+    pub hard_tabs: bool,
+    /// Whether the text is highlighted as markdown.
+    pub(crate) markdown: bool,
+    pub(crate) highlighter: MarkdownHighlighter,
+    pub path: Option<PathBuf>,
+    /// The document as it was last loaded or saved.
+    pub(crate) saved_content: Buffer,
+    /// Counts changes to the document. Unlike the buffer's version it keeps
+    /// counting when `set_text` replaces the buffer.
+    revision: usize,
+    pub(crate) search: Option<SearchQuery>,
+    /// Byte ranges of the document matching `search`, in order. Edits mark
+    /// them stale, and `sync_matches` searches the edited text again once
+    /// per action.
+    pub(crate) matches: Matches,
+    _subscriptions: Vec<Subscription>,
 }
 
 impl TextInput {
-    pub fn left(&mut self, _: &Left, cx: &mut ViewContext<Self>) {
-        if self.content_idx > 0 && self.cursor_offset() == 0 {
-            self.move_up(cx);
-            self.cursor_to_end(cx);
-        } else if self.content[self.content_idx].selected_range.is_empty() {
-            self.move_x(self.previous_boundary(self.cursor_offset()), cx);
-        } else {
-            self.move_x(self.content[self.content_idx].selected_range.start, cx)
+    /// Creates an empty input. Takes its focus handle from `cx` and subscribes
+    /// to focus and blur so it can emit `TextInputEvent::Focused` and
+    /// `TextInputEvent::Blurred`.
+    pub fn new(cx: &mut ViewContext<Self>) -> Self {
+        let focus_handle = cx.focus_handle();
+        let _subscriptions = vec![
+            cx.on_focus(&focus_handle, |_, cx| cx.emit(TextInputEvent::Focused)),
+            cx.on_blur(&focus_handle, |_, cx| cx.emit(TextInputEvent::Blurred)),
+        ];
+        Self {
+            focus_handle,
+            document: Document::default(),
+            state: EditorState::default(),
+            history: History::new(),
+            last_layouts: HashMap::new(),
+            scroll_handle: UniformListScrollHandle::new(),
+            display_map: DisplayMap::new(1),
+            wrap_width: None,
+            text_style: None,
+            wrap_task: None,
+            tab_width: 4,
+            hard_tabs: false,
+            markdown: true,
+            highlighter: MarkdownHighlighter::new(1),
+            path: None,
+            saved_content: Buffer::new(),
+            revision: 0,
+            search: None,
+            matches: Matches::default(),
+            _subscriptions,
         }
     }
 
+    pub fn with_text(mut self, text: &str) -> Self {
+        self.set_text(text);
+        self
+    }
+
+    /// Sets the file the document is saved to without reading it, for files
+    /// that do not exist yet.
+    pub fn with_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub fn with_tab_width(mut self, tab_width: usize) -> Self {
+        self.set_tab_width(tab_width);
+        self
+    }
+
+    pub fn with_hard_tabs(mut self, hard_tabs: bool) -> Self {
+        self.hard_tabs = hard_tabs;
+        self
+    }
+
+    /// Turns markdown highlighting on or off, e.g. off for plain text
+    /// fields such as the find bar's query.
+    pub fn with_markdown(mut self, markdown: bool) -> Self {
+        self.markdown = markdown;
+        self
+    }
+
+    /// Changes whenever the document does, for views that cache something
+    /// derived from it.
+    pub fn revision(&self) -> usize {
+        self.revision
+    }
+
+    pub fn tab_width(&self) -> usize {
+        self.tab_width
+    }
+
+    /// Changes the distance between tab stops. Lines containing tabs are
+    /// laid out again, so every line is rewrapped.
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        self.tab_width = tab_width.max(1);
+        self.last_layouts.clear();
+        self.display_map.invalidate_all();
+    }
+
+    /// Replaces the whole document, resetting the selection and the undo
+    /// history. The line endings of `text` are kept for `to_string`.
+    pub fn set_text(&mut self, text: &str) {
+        self.document = Document::new(text);
+        self.revision += 1;
+        self.saved_content = self.document.buffer.clone();
+        self.state = EditorState::default();
+        self.history = History::new();
+        self.last_layouts.clear();
+        self.display_map = DisplayMap::new(self.document.buffer.line_count());
+        self.highlighter = MarkdownHighlighter::new(self.document.buffer.line_count());
+        self.scroll_handle.scroll_to_item(0);
+        self.refresh_matches();
+    }
+
+    pub fn left(&mut self, _: &Left, cx: &mut ViewContext<Self>) {
+        self.move_selections(
+            |this, selection| {
+                if selection.is_empty() {
+                    this.document.buffer.previous_boundary(selection.head)
+                } else {
+                    selection.start()
+                }
+            },
+            cx,
+        );
+    }
+
     pub fn right(&mut self, _: &Right, cx: &mut ViewContext<Self>) {
-        if self.content_idx < self.content.len() - 1
-            && self.cursor_offset() == self.content[self.content_idx].content.len()
-        {
-            self.move_down(cx);
-            self.cursor_to_start(cx);
-        } else if self.content[self.content_idx].selected_range.is_empty() {
-            self.move_x(self.next_boundary(self.cursor_offset()), cx);
-        } else {
-            self.move_x(self.content[self.content_idx].selected_range.end, cx)
-        }
+        self.move_selections(
+            |this, selection| {
+                if selection.is_empty() {
+                    this.document.buffer.next_boundary(selection.head)
+                } else {
+                    selection.end()
+                }
+            },
+            cx,
+        );
     }
 
     pub fn up(&mut self, _: &Up, cx: &mut ViewContext<Self>) {
-        if self.content_idx > 0 {
-            self.move_up(cx);
-            if self.content[self.content_idx].content.len() < self.cursor_offset() {
-                self.cursor_to_end(cx);
-            }
-        }
+        self.move_vertically(-1, false, cx);
     }
 
     pub fn down(&mut self, _: &Down, cx: &mut ViewContext<Self>) {
-        if self.content.len() - 1 > self.content_idx {
-            self.move_down(cx);
-            if self.content[self.content_idx].content.len() < self.cursor_offset() {
-                self.cursor_to_end(cx);
-            }
-        }
+        self.move_vertically(1, false, cx);
+    }
+
+    pub fn select_up(&mut self, _: &SelectUp, cx: &mut ViewContext<Self>) {
+        self.move_vertically(-1, true, cx);
+    }
+
+    pub fn select_down(&mut self, _: &SelectDown, cx: &mut ViewContext<Self>) {
+        self.move_vertically(1, true, cx);
+    }
+
+    pub fn page_up(&mut self, _: &PageUp, cx: &mut ViewContext<Self>) {
+        self.move_by_page(-1, false, cx);
+    }
+
+    pub fn page_down(&mut self, _: &PageDown, cx: &mut ViewContext<Self>) {
+        self.move_by_page(1, false, cx);
+    }
+
+    pub fn select_page_up(&mut self, _: &SelectPageUp, cx: &mut ViewContext<Self>) {
+        self.move_by_page(-1, true, cx);
+    }
+
+    pub fn select_page_down(&mut self, _: &SelectPageDown, cx: &mut ViewContext<Self>) {
+        self.move_by_page(1, true, cx);
     }
 
     pub fn select_left(&mut self, _: &SelectLeft, cx: &mut ViewContext<Self>) {
-        self.select_to(
-            self.previous_boundary(self.cursor_offset()),
-            self.content_idx,
+        self.select_heads(
+            |this, head| this.document.buffer.previous_boundary(head),
             cx,
         );
     }
 
     pub fn select_right(&mut self, _: &SelectRight, cx: &mut ViewContext<Self>) {
-        self.select_to(
-            self.next_boundary(self.cursor_offset()),
-            self.content_idx,
+        self.select_heads(|this, head| this.document.buffer.next_boundary(head), cx);
+    }
+
+    pub fn move_word_left(&mut self, _: &MoveWordLeft, cx: &mut ViewContext<Self>) {
+        self.move_selections(
+            |this, selection| this.document.buffer.previous_word_boundary(selection.head),
+            cx,
+        );
+    }
+
+    pub fn move_word_right(&mut self, _: &MoveWordRight, cx: &mut ViewContext<Self>) {
+        self.move_selections(
+            |this, selection| this.document.buffer.next_word_boundary(selection.head),
+            cx,
+        );
+    }
+
+    pub fn select_word_left(&mut self, _: &SelectWordLeft, cx: &mut ViewContext<Self>) {
+        self.select_heads(
+            |this, head| this.document.buffer.previous_word_boundary(head),
+            cx,
+        );
+    }
+
+    pub fn select_word_right(&mut self, _: &SelectWordRight, cx: &mut ViewContext<Self>) {
+        self.select_heads(
+            |this, head| this.document.buffer.next_word_boundary(head),
             cx,
         );
     }
 
+    pub fn delete_word_backward(&mut self, _: &DeleteWordBackward, cx: &mut ViewContext<Self>) {
+        self.delete_to(
+            |this, head| this.document.buffer.previous_word_boundary(head),
+            cx,
+        )
+    }
+
+    pub fn delete_word_forward(&mut self, _: &DeleteWordForward, cx: &mut ViewContext<Self>) {
+        self.delete_to(
+            |this, head| this.document.buffer.next_word_boundary(head),
+            cx,
+        )
+    }
+
     pub fn select_all(&mut self, _: &SelectAll, cx: &mut ViewContext<Self>) {
-        self.move_x(0, cx);
-        self.select_to(self.content.len(), self.content_idx, cx)
+        self.set_selection(
+            Selection::new(TextPosition::default(), self.document.buffer.max_position()),
+            cx,
+        );
+    }
+
+    /// Adds the next occurrence of the newest selection's text as a new
+    /// selection, wrapping around the document. An empty selection first
+    /// selects the word it is in.
+    pub fn select_next_occurrence(&mut self, _: &SelectNextOccurrence, cx: &mut ViewContext<Self>) {
+        let newest = self.state.selection();
+        if newest.is_empty() {
+            self.set_newest_selection(self.word_at(newest.head), cx);
+            return;
+        }
+
+        let text = self.document.buffer.to_string();
+        let range = self.selected_range();
+        let needle = &text[range.clone()];
+        let selected = self
+            .state
+            .selections
+            .iter()
+            .map(|selection| self.offset_range(selection))
+            .collect::<Vec<_>>();
+        let next = text[range.end..]
+            .match_indices(needle)
+            .map(|(ix, _)| range.end + ix)
+            .chain(text[..range.start].match_indices(needle).map(|(ix, _)| ix))
+            .map(|start| start..start + needle.len())
+            .find(|occurrence| !selected.contains(occurrence));
+        if let Some(next) = next {
+            let mut selections = self.state.selections.as_slice().to_vec();
+            selections.push(Selection::new(
+                self.document.buffer.position_for_offset(next.start),
+                self.document.buffer.position_for_offset(next.end),
+            ));
+            let newest = selections.len() - 1;
+            self.set_selections(SelectionSet::new(selections, newest), cx);
+        }
+    }
+
+    /// Drops every selection but the newest. With a single selection the
+    /// action is left to the containing view, e.g. to close the find bar.
+    pub fn cancel(&mut self, _: &Cancel, cx: &mut ViewContext<Self>) {
+        if self.state.selections.len() > 1 {
+            self.set_selection(self.state.selection(), cx);
+        } else {
+            cx.propagate();
+        }
     }
 
     pub fn home(&mut self, _: &Home, cx: &mut ViewContext<Self>) {
-        self.move_x(0, cx);
+        self.move_selections(|_, selection| TextPosition::new(selection.head.line, 0), cx);
     }
 
     pub fn end(&mut self, _: &End, cx: &mut ViewContext<Self>) {
-        self.move_x(self.content.len(), cx);
+        self.move_selections(|this, selection| this.line_end(selection.head), cx);
+    }
+
+    pub fn select_to_line_start(&mut self, _: &SelectToLineStart, cx: &mut ViewContext<Self>) {
+        self.select_heads(|_, head| TextPosition::new(head.line, 0), cx);
+    }
+
+    pub fn select_to_line_end(&mut self, _: &SelectToLineEnd, cx: &mut ViewContext<Self>) {
+        self.select_heads(|this, head| this.line_end(head), cx);
+    }
+
+    pub fn move_to_document_start(&mut self, _: &MoveToDocumentStart, cx: &mut ViewContext<Self>) {
+        self.move_to(TextPosition::default(), cx);
+    }
+
+    pub fn move_to_document_end(&mut self, _: &MoveToDocumentEnd, cx: &mut ViewContext<Self>) {
+        self.move_to(self.document.buffer.max_position(), cx);
+    }
+
+    pub fn select_to_document_start(
+        &mut self,
+        _: &SelectToDocumentStart,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let anchor = self.state.selection().anchor;
+        self.set_selection(Selection::new(anchor, TextPosition::default()), cx);
+    }
+
+    pub fn select_to_document_end(&mut self, _: &SelectToDocumentEnd, cx: &mut ViewContext<Self>) {
+        let anchor = self.state.selection().anchor;
+        self.set_selection(
+            Selection::new(anchor, self.document.buffer.max_position()),
+            cx,
+        );
     }
 
     pub fn backspace(&mut self, _: &Backspace, cx: &mut ViewContext<Self>) {
-        if self.content[self.content_idx].selected_range.is_empty() {
-            if self.cursor_offset() == 0 && self.content_idx > 0 {
-                let current_content = self.content[self.content_idx].content.clone();
-                let previous_content = self.content[self.content_idx - 1].content.clone();
+        self.delete_to(
+            |this, head| this.document.buffer.previous_boundary(head),
+            cx,
+        )
+    }
 
-                self.move_x(previous_content.len(), cx);
+    pub fn delete(&mut self, _: &Delete, cx: &mut ViewContext<Self>) {
+        self.delete_to(|this, head| this.document.buffer.next_boundary(head), cx)
+    }
 
-                let merged_content = previous_content.to_string() + &current_content;
-                self.content[self.content_idx - 1].content = merged_content.into();
+    pub fn undo(&mut self, _: &Undo, cx: &mut ViewContext<Self>) {
+        if let Some(transaction) = self.history.undo(self.state.selections.clone()) {
+            let (edits, selections) = (
+                transaction.edits.clone(),
+                transaction.selection_before.clone(),
+            );
+            for edit in edits.iter().rev() {
+                self.replace_in_buffer(edit.new_range(), &edit.old_text);
+                cx.emit(TextInputEvent::Edited {
+                    range: edit.new_range(),
+                    old_text: edit.new_text.clone(),
+                    new_text: edit.old_text.clone(),
+                });
+            }
+            self.state.marked_range = None;
+            self.set_selections(selections, cx);
+        }
+    }
 
-                self.content.remove(self.content_idx);
+    pub fn redo(&mut self, _: &Redo, cx: &mut ViewContext<Self>) {
+        if let Some(transaction) = self.history.redo() {
+            let (edits, selections) = (
+                transaction.edits.clone(),
+                transaction.selection_after.clone(),
+            );
+            for edit in &edits {
+                self.replace_in_buffer(edit.range.clone(), &edit.new_text);
+                cx.emit(TextInputEvent::Edited {
+                    range: edit.range.clone(),
+                    old_text: edit.old_text.clone(),
+                    new_text: edit.new_text.clone(),
+                });
+            }
+            self.state.marked_range = None;
+            self.sync_matches();
+            if let Some(selections) = selections {
+                self.set_selections(selections, cx);
+            }
+            cx.notify();
+        }
+    }
 
-                self.move_up(cx);
-                let content_len = self.content[self.content_idx].content.len();
-                self.content[self.content_idx].selected_range = content_len..content_len;
+    /// Copies the selected text, one line per selection when there are
+    /// several.
+    pub fn copy(&mut self, _: &Copy, cx: &mut ViewContext<Self>) {
+        let texts = self
+            .state
+            .selections
+            .iter()
+            .filter(|selection| !selection.is_empty())
+            .map(|selection| {
+                self.document
+                    .buffer
+                    .text_for_range(self.offset_range(selection))
+            })
+            .collect::<Vec<_>>();
+        if !texts.is_empty() {
+            cx.write_to_clipboard(ClipboardItem::new_string(texts.join("\n")));
+        }
+    }
+
+    pub fn cut(&mut self, _: &Cut, cx: &mut ViewContext<Self>) {
+        if self
+            .state
+            .selections
+            .iter()
+            .any(|selection| !selection.is_empty())
+        {
+            self.copy(&Copy, cx);
+            self.history.finalize_last_transaction();
+            self.replace_selections("", cx);
+            self.history.finalize_last_transaction();
+        }
+    }
+
+    /// Pastes at every selection. Text with one line per selection, as
+    /// copied from that many selections, is split across them instead.
+    pub fn paste(&mut self, _: &Paste, cx: &mut ViewContext<Self>) {
+        if let Some(text) = cx.read_from_clipboard().and_then(|item| item.text()) {
+            let text = text.replace("\r\n", "\n");
+            let lines = text.split('\n').collect::<Vec<_>>();
+            let count = self.state.selections.len();
+            self.history.finalize_last_transaction();
+            if count > 1 && lines.len() == count {
+                self.replace_selections_with(|ix| lines[ix].to_owned(), cx);
             } else {
-                self.select_to(
-                    self.previous_boundary(self.cursor_offset()),
-                    self.content_idx,
-                    cx,
-                );
+                self.replace_selections(&text, cx);
             }
+            self.history.finalize_last_transaction();
         }
+    }
 
-        self.replace_text_in_range(None, "", cx);
+    /// Replaces the document with the contents of `path`. The file's line
+    /// endings are remembered and used again when it is saved.
+    pub fn load(&mut self, path: PathBuf, cx: &mut ViewContext<Self>) -> io::Result<()> {
+        let text = fs::read_to_string(&path)?;
+        let old_text = self.document.buffer.to_string();
+        self.set_text(&text);
+        self.path = Some(path);
+        cx.emit(TextInputEvent::Edited {
+            range: 0..old_text.len(),
+            old_text,
+            new_text: self.document.buffer.to_string(),
+        });
+        cx.emit(TextInputEvent::SelectionChanged);
+        cx.notify();
+        Ok(())
     }
 
-    pub fn delete(&mut self, _: &Delete, cx: &mut ViewContext<Self>) {
-        if self.content[self.content_idx].selected_range.is_empty() {
-            self.select_to(
-                self.next_boundary(self.cursor_offset()),
-                self.content_idx,
-                cx,
-            )
-        }
-        self.replace_text_in_range(None, "", cx)
+    /// Writes the document to `path` and makes it the file being edited.
+    pub fn write_to(&mut self, path: PathBuf, cx: &mut ViewContext<Self>) -> io::Result<()> {
+        fs::write(&path, self.to_string())?;
+        self.saved_content = self.document.buffer.clone();
+        self.path = Some(path);
+        cx.notify();
+        Ok(())
     }
 
-    pub fn on_mouse_down(&mut self, event: &MouseDownEvent, cx: &mut ViewContext<Self>) {
-        self.content[self.content_idx].is_selecting = true;
+    /// Whether the document differs from the file, which it no longer does
+    /// once edits are undone back to the saved text.
+    pub fn is_dirty(&self) -> bool {
+        self.document.buffer.version() != self.saved_content.version()
+            && !self.document.buffer.same_text(&self.saved_content)
+    }
 
-        if event.modifiers.shift {
-            self.select_to(
-                self.index_for_mouse_position(event.position).0,
-                self.index_for_mouse_position(event.position).1,
-                cx,
-            );
+    /// File name of the document followed by a dot while it has unsaved
+    /// changes.
+    pub fn title(&self) -> String {
+        let name = self.title_name();
+        if self.is_dirty() {
+            format!("{name} \u{2022}")
         } else {
-            let pos = self.index_for_mouse_position(event.position);
-            self.move_x(pos.0, cx);
-            self.move_y(pos.1, cx);
+            name
         }
     }
 
+    /// File name of the document, or "Untitled" before it is first saved.
+    pub fn title_name(&self) -> String {
+        self.path
+            .as_ref()
+            .and_then(|path| path.file_name())
+            .map_or("Untitled".into(), |name| {
+                name.to_string_lossy().into_owned()
+            })
+    }
+
+    pub fn on_mouse_down(&mut self, event: &MouseDownEvent, cx: &mut ViewContext<Self>) {
+        self.state.is_selecting = true;
+        self.sync_display_map(cx);
+
+        let position = self.index_for_mouse_position(event.position);
+        let mode = match (
+            event.click_count,
+            self.row_and_x_for_mouse_position(event.position),
+        ) {
+            (2, _) => SelectMode::Word(self.word_at(position)),
+            (3, _) => SelectMode::Line(self.line_at(position)),
+            (_, Some((row, _, x))) if event.modifiers.alt => SelectMode::Column {
+                row,
+                x,
+                base: self.state.selections.as_slice().to_vec(),
+            },
+            _ => SelectMode::Character,
+        };
+        match &mode {
+            SelectMode::Word(selection) | SelectMode::Line(selection) => {
+                self.set_newest_selection(*selection, cx)
+            }
+            // Alt-click adds a cursor, and dragging from it selects a block.
+            SelectMode::Column { base, .. } => {
+                let mut selections = base.clone();
+                selections.push(Selection::cursor(position));
+                let newest = selections.len() - 1;
+                self.set_selections(SelectionSet::new(selections, newest), cx);
+            }
+            SelectMode::Character if event.modifiers.shift => self.select_to(position, cx),
+            SelectMode::Character => self.move_to(position, cx),
+        }
+        self.state.select_mode = mode;
+    }
+
     pub fn on_mouse_up(&mut self, _: &MouseUpEvent, _: &mut ViewContext<Self>) {
-        self.content[self.content_idx].is_selecting = false;
+        self.state.is_selecting = false;
     }
 
     pub fn on_mouse_move(&mut self, event: &MouseMoveEvent, cx: &mut ViewContext<Self>) {
-        if self.content[self.content_idx].is_selecting {
-            self.select_to(
-                self.index_for_mouse_position(event.position).0,
-                self.index_for_mouse_position(event.position).1,
-                cx,
-            );
+        if self.state.is_selecting {
+            self.sync_display_map(cx);
+            let position = self.index_for_mouse_position(event.position);
+            let (origin, unit) = match self.state.select_mode.clone() {
+                SelectMode::Character => return self.select_to(position, cx),
+                SelectMode::Word(origin) => (origin, self.word_at(position)),
+                SelectMode::Line(origin) => (origin, self.line_at(position)),
+                SelectMode::Column { row, x, base } => {
+                    return self.select_block(row, x, base, event.position, cx)
+                }
+            };
+            let selection = if unit.start() < origin.start() {
+                Selection::new(origin.end(), unit.start())
+            } else {
+                Selection::new(origin.start(), unit.end())
+            };
+            self.set_newest_selection(selection, cx);
         }
     }
 
@@ -183,245 +653,949 @@ impl TextInput {
         cx.show_character_palette();
     }
 
+    /// Splits the line at each selection. On a markdown list item the new
+    /// line starts the next item, and on an empty item the marker is removed
+    /// instead, ending the list.
     pub fn enter(&mut self, _: &Enter, cx: &mut ViewContext<Self>) {
-        let leftovers = if self.content[self.content_idx].content.len() > 0 {
-            self.content[self.content_idx].content
-                [self.cursor_offset()..self.content[self.content_idx].content.len()]
-                .to_string()
+        let selections_before = self.state.selections.clone();
+        self.select_empty_to(|this, head| match this.list_continuation(head) {
+            Some(ListContinuation::End) => TextPosition::new(head.line, 0),
+            _ => head,
+        });
+        let texts = self
+            .state
+            .selections
+            .iter()
+            .map(|selection| {
+                let (start, end) = (selection.start(), selection.end());
+                match self.list_continuation(end) {
+                    Some(ListContinuation::Item(prefix)) if selection.is_empty() => {
+                        format!("\n{prefix}")
+                    }
+                    Some(ListContinuation::End) if start == TextPosition::new(end.line, 0) => {
+                        String::new()
+                    }
+                    _ => "\n".to_owned(),
+                }
+            })
+            .collect::<Vec<_>>();
+        self.replace_extended_selections(selections_before, |ix| texts[ix].clone(), cx);
+    }
+
+    /// How enter at `position` continues a markdown list, if the position is
+    /// in a list item outside of a code block.
+    fn list_continuation(&self, position: TextPosition) -> Option<ListContinuation> {
+        let in_code = self
+            .highlighter
+            .spans(position.line)
+            .iter()
+            .any(|(_, token)| matches!(token, Token::CodeBlock | Token::CodeFence));
+        if !self.markdown || in_code {
+            return None;
+        }
+        list_continuation(&self.document.buffer.line(position.line), position.column)
+    }
+
+    /// Indents the selected lines when a selection spans several of them.
+    /// Otherwise inserts a tab at each selection, or with soft tabs as many
+    /// spaces as it takes to reach the next tab stop.
+    pub fn tab(&mut self, _: &Tab, cx: &mut ViewContext<Self>) {
+        if self
+            .state
+            .selections
+            .iter()
+            .any(|selection| selection.start().line != selection.end().line)
+        {
+            self.indent(&Indent, cx);
+        } else if self.hard_tabs {
+            self.replace_selections("\t", cx);
         } else {
-            "".to_string()
-        };
+            let indents = self
+                .state
+                .selections
+                .iter()
+                .map(|selection| {
+                    let start = selection.start();
+                    let line = self.document.buffer.line(start.line);
+                    " ".repeat(soft_tab_len(&line[..start.column], self.tab_width))
+                })
+                .collect::<Vec<_>>();
+            self.replace_selections_with(|ix| indents[ix].clone(), cx);
+        }
+    }
 
-        let new_content =
-            self.content[self.content_idx].content[..self.cursor_offset()].to_string();
-        self.content[self.content_idx].content = new_content.into();
-        self.new_line(leftovers, self.content_idx + 1, cx);
-
-        self.move_down(cx);
-        self.cursor_to_start(cx);
-    }
-
-    pub fn new_line(&mut self, data: String, index: usize, _cx: &mut ViewContext<Self>) {
-        self.content.insert(
-            index,
-            TextLine {
-                content: data.into(),
-                selected_range: 0..0,
-                selection_reversed: false,
-                marked_range: None,
-                last_layout: None,
-                is_selecting: false,
-            },
-        );
+    /// Adds a level of indentation to the start of every selected line.
+    pub fn indent(&mut self, _: &Indent, cx: &mut ViewContext<Self>) {
+        let unit = if self.hard_tabs {
+            "\t".to_owned()
+        } else {
+            " ".repeat(self.tab_width)
+        };
+        let lines = self
+            .state
+            .selections
+            .lines()
+            .into_iter()
+            .map(|line| (line, unit.len()))
+            .collect::<Vec<_>>();
+        let selections = self.state.selections.clone();
+        self.history.finalize_last_transaction();
+        for &(line, _) in lines.iter().rev() {
+            let start = self.document.buffer.line_start(line);
+            self.edit(start..start, &unit, &selections, cx);
+        }
+        self.history.finalize_last_transaction();
+        let shifted = self.state.selections.shift_lines(&lines, true);
+        self.set_selections(shifted, cx);
     }
 
-    fn move_x(&mut self, offset: usize, cx: &mut ViewContext<Self>) {
-        self.content[self.content_idx].selected_range = offset..offset;
-        cx.notify()
+    /// Removes a level of indentation, a tab or up to a tab width of spaces,
+    /// from the start of every selected line.
+    pub fn outdent(&mut self, _: &Outdent, cx: &mut ViewContext<Self>) {
+        let lines = self
+            .state
+            .selections
+            .lines()
+            .into_iter()
+            .filter_map(|line| {
+                let len = outdent_len(&self.document.buffer.line(line), self.tab_width);
+                (len > 0).then_some((line, len))
+            })
+            .collect::<Vec<_>>();
+        if lines.is_empty() {
+            return;
+        }
+        let selections = self.state.selections.clone();
+        self.history.finalize_last_transaction();
+        for &(line, len) in lines.iter().rev() {
+            let start = self.document.buffer.line_start(line);
+            self.edit(start..start + len, "", &selections, cx);
+        }
+        self.history.finalize_last_transaction();
+        let shifted = self.state.selections.shift_lines(&lines, false);
+        self.set_selections(shifted, cx);
     }
 
-    fn move_y(&mut self, offset: usize, cx: &mut ViewContext<Self>) {
-        self.content_idx = offset;
+    /// Highlights every match of `query` and selects the first one at or
+    /// after the selection, so the search narrows as the query is typed.
+    /// `None` clears the search.
+    pub fn set_search(&mut self, query: Option<SearchQuery>, cx: &mut ViewContext<Self>) {
+        self.search = query;
+        self.refresh_matches();
+        let start = self.selected_range().start;
+        let ix = self
+            .matches
+            .as_slice()
+            .partition_point(|range| range.start < start);
+        if !self.matches.as_slice().is_empty() {
+            self.select_match(ix % self.matches.as_slice().len(), cx);
+        }
         cx.notify();
     }
 
-    fn move_up(&mut self, cx: &mut ViewContext<Self>) {
-        self.content_idx -= 1;
-        cx.notify();
+    pub fn search(&self) -> Option<&SearchQuery> {
+        self.search.as_ref()
     }
 
-    fn move_down(&mut self, cx: &mut ViewContext<Self>) {
-        self.content_idx += 1;
-        cx.notify();
+    pub fn match_count(&self) -> usize {
+        self.matches.as_slice().len()
     }
 
-    pub fn cursor_to_end(&mut self, cx: &mut ViewContext<Self>) {
-        let length = self.content[self.content_idx].content.len();
-        self.move_x(length, cx);
+    /// Index of the match that is currently selected, if any.
+    pub fn active_match(&self) -> Option<usize> {
+        let selected = self.selected_range();
+        let ix = self
+            .matches
+            .as_slice()
+            .partition_point(|range| range.start < selected.start);
+        (self.matches.as_slice().get(ix) == Some(&selected)).then_some(ix)
     }
 
-    pub fn cursor_to_start(&mut self, cx: &mut ViewContext<Self>) {
-        self.move_x(0, cx);
+    /// Selects the first match after the selection, wrapping around to the
+    /// start of the document. Without a search the action is left to the
+    /// containing view, such as the find bar this input is the query of.
+    pub fn find_next(&mut self, _: &FindNext, cx: &mut ViewContext<Self>) {
+        if self.search.is_none() {
+            cx.propagate();
+            return;
+        }
+        if !self.matches.as_slice().is_empty() {
+            let end = self.selected_range().end;
+            let ix = self
+                .matches
+                .as_slice()
+                .partition_point(|range| range.start < end);
+            self.select_match(ix % self.matches.as_slice().len(), cx);
+        }
     }
 
-    pub fn cursor_offset(&self) -> usize {
-        if self.content[self.content_idx].selection_reversed {
-            self.content[self.content_idx].selected_range.start
-        } else {
-            self.content[self.content_idx].selected_range.end
+    pub fn find_previous(&mut self, _: &FindPrevious, cx: &mut ViewContext<Self>) {
+        if self.search.is_none() {
+            cx.propagate();
+            return;
+        }
+        if !self.matches.as_slice().is_empty() {
+            let start = self.selected_range().start;
+            let ix = self
+                .matches
+                .as_slice()
+                .partition_point(|range| range.start < start);
+            let ix = ix
+                .checked_sub(1)
+                .unwrap_or(self.matches.as_slice().len() - 1);
+            self.select_match(ix, cx);
         }
     }
 
-    fn index_for_mouse_position(&self, position: Point<Pixels>) -> (usize, usize) {
-        let mut y = ((position.y.0 + 4.) / 14. - 1.).floor() as usize;
-        y = y.min(self.content.len() - 1);
+    /// Replaces the selected match, expanding capture groups in
+    /// `replacement`, and selects the next match. When no match is selected
+    /// this only moves to the next one, so the user sees what gets replaced.
+    pub fn replace_next_match(&mut self, replacement: &str, cx: &mut ViewContext<Self>) {
+        let Some(query) = self.search.clone() else {
+            return;
+        };
+        if let Some(ix) = self.active_match() {
+            let range = self.matches.as_slice()[ix].clone();
+            let new_text = query.expand(
+                &self.document.buffer.to_string(),
+                range.clone(),
+                replacement,
+            );
+            self.history.finalize_last_transaction();
+            self.replace_range(range, &new_text, cx);
+            self.history.finalize_last_transaction();
+        }
+        self.find_next(&FindNext, cx);
+    }
 
-        let (Some(bounds), Some(line)) = (
-            self.last_bounds.as_ref(),
-            self.content[self.content_idx].last_layout.as_ref(),
-        ) else {
-            return (0, y);
+    /// Replaces every match at once. The replacements are applied as a single
+    /// edit spanning the first to the last match, so one undo reverts them
+    /// all, even where matches span lines.
+    pub fn replace_all_matches(&mut self, replacement: &str, cx: &mut ViewContext<Self>) {
+        let Some(query) = self.search.clone() else {
+            return;
+        };
+        let Some((span, new_text)) =
+            query.replace_all_edit(&self.document.buffer.to_string(), replacement)
+        else {
+            return;
         };
 
-        let mut x = line.closest_index_for_x(position.x - bounds.left());
-        x = x.min(self.content[y].content.len());
-        (x, y)
+        self.history.finalize_last_transaction();
+        self.replace_range(span, &new_text, cx);
+        self.history.finalize_last_transaction();
     }
 
-    fn select_to(&mut self, x_offset: usize, _y_offset: usize, cx: &mut ViewContext<Self>) {
-        if self.content[self.content_idx].selection_reversed {
-            self.content[self.content_idx].selected_range.start = x_offset
-        } else {
-            self.content[self.content_idx].selected_range.end = x_offset
-        };
-        if self.content[self.content_idx].selected_range.end
-            < self.content[self.content_idx].selected_range.start
-        {
-            self.content[self.content_idx].selection_reversed =
-                !self.content[self.content_idx].selection_reversed;
-            self.content[self.content_idx].selected_range =
-                self.content[self.content_idx].selected_range.end
-                    ..self.content[self.content_idx].selected_range.start;
-        }
-        cx.notify()
+    fn select_match(&mut self, ix: usize, cx: &mut ViewContext<Self>) {
+        let range = self.matches.as_slice()[ix].clone();
+        self.set_selection(
+            Selection::new(
+                self.document.buffer.position_for_offset(range.start),
+                self.document.buffer.position_for_offset(range.end),
+            ),
+            cx,
+        );
     }
 
-    fn offset_from_utf16(&self, offset: usize) -> usize {
-        let mut utf8_offset = 0;
-        let mut utf16_count = 0;
+    fn refresh_matches(&mut self) {
+        self.matches = match &self.search {
+            Some(query) => Matches::new(Some(query), &self.document.buffer.to_string()),
+            None => Matches::default(),
+        };
+    }
 
-        for ch in self.content[self.content_idx].content.chars() {
-            if utf16_count >= offset {
-                break;
+    /// Searches the text edited since the matches were last synced.
+    fn sync_matches(&mut self) {
+        if let Some(query) = &self.search {
+            if self.matches.is_stale() {
+                self.matches.sync(query, &self.document.buffer.to_string());
             }
-            utf16_count += ch.len_utf16();
-            utf8_offset += ch.len_utf8();
         }
-
-        utf8_offset
     }
 
-    fn offset_to_utf16(&self, offset: usize) -> usize {
-        let mut utf16_offset = 0;
-        let mut utf8_count = 0;
-
-        for ch in self.content[self.content_idx].content.chars() {
-            if utf8_count >= offset {
-                break;
-            }
-            utf8_count += ch.len_utf8();
-            utf16_offset += ch.len_utf16();
+    pub fn line(&self, index: usize) -> TextLine {
+        TextLine {
+            content: self.document.buffer.line(index).into(),
         }
+    }
 
-        utf16_offset
+    /// Byte range of the newest selection in the document.
+    pub fn selected_range(&self) -> Range<usize> {
+        self.offset_range(&self.state.selection())
     }
 
-    pub fn range_to_utf16(&self, range: &Range<usize>) -> Range<usize> {
-        self.offset_to_utf16(range.start)..self.offset_to_utf16(range.end)
+    fn offset_range(&self, selection: &Selection) -> Range<usize> {
+        self.document.buffer.offset_for_position(selection.start())
+            ..self.document.buffer.offset_for_position(selection.end())
     }
 
-    pub fn range_from_utf16(&self, range_utf16: &Range<usize>) -> Range<usize> {
-        self.offset_from_utf16(range_utf16.start)..self.offset_from_utf16(range_utf16.end)
+    /// Replaces every selection with `new_text` and puts a cursor after each
+    /// insertion.
+    fn replace_selections(&mut self, new_text: &str, cx: &mut ViewContext<Self>) {
+        self.replace_selections_with(|_| new_text.to_owned(), cx);
     }
 
-    fn previous_boundary(&self, offset: usize) -> usize {
-        self.content[self.content_idx]
-            .content
-            .grapheme_indices(true)
-            .rev()
-            .find_map(|(idx, _)| (idx < offset).then_some(idx))
-            .unwrap_or(0)
+    /// Replaces the selection at each index with the text `text_for` gives
+    /// for it. The edits are applied last to first, so the ranges of the
+    /// ones still to go stay valid, and undo as one step. Edits that split or
+    /// join lines are kept out of the surrounding typing transaction so that
+    /// they undo as a step of their own.
+    fn replace_selections_with(
+        &mut self,
+        text_for: impl Fn(usize) -> String,
+        cx: &mut ViewContext<Self>,
+    ) {
+        self.replace_extended_selections(self.state.selections.clone(), text_for, cx);
     }
 
-    fn next_boundary(&self, offset: usize) -> usize {
-        self.content[self.content_idx]
-            .content
-            .grapheme_indices(true)
-            .find_map(|(idx, _)| (idx > offset).then_some(idx))
-            .unwrap_or(self.content[self.content_idx].content.len())
+    /// Deletes every selection, extending empty ones to the position `f`
+    /// gives first.
+    fn delete_to(
+        &mut self,
+        f: impl FnMut(&Self, TextPosition) -> TextPosition,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let selections_before = self.state.selections.clone();
+        self.select_empty_to(f);
+        self.replace_extended_selections(selections_before, |_| String::new(), cx);
     }
 
-    pub fn add_word_to_start_of_line(
+    /// Like `replace_selections_with`, for selections that were extended
+    /// over more than the user selected, such as the character a backspace
+    /// deletes. Undo restores `selections_before` rather than the extended
+    /// selections.
+    fn replace_extended_selections(
         &mut self,
-        word: &str,
-        index: usize,
+        selections_before: SelectionSet,
+        text_for: impl Fn(usize) -> String,
         cx: &mut ViewContext<Self>,
     ) {
-        if self.content.len() <= index {
-            self.new_line("".into(), index, cx);
+        let edits = self
+            .state
+            .selections
+            .iter()
+            .enumerate()
+            .map(|(ix, selection)| (self.offset_range(selection), text_for(ix)))
+            .collect::<Vec<_>>();
+        let changes_lines = edits.iter().any(|(range, new_text)| {
+            new_text.contains('\n')
+                || self.document.buffer.line_for_offset(range.start)
+                    != self.document.buffer.line_for_offset(range.end)
+        });
+
+        if changes_lines {
+            self.history.finalize_last_transaction();
+        }
+        for (range, new_text) in edits.iter().rev() {
+            if !range.is_empty() || !new_text.is_empty() {
+                self.edit(range.clone(), new_text, &selections_before, cx);
+            }
+        }
+        if changes_lines {
+            self.history.finalize_last_transaction();
         }
 
-        let new_content = if self.content[index].content.len() > 0 {
-            word.to_owned() + " " + &self.content[index].content
-        } else {
-            word.to_owned() + &self.content[index].content
-        };
+        self.state.marked_range.take();
+        let mut shift = 0isize;
+        let cursors = edits
+            .iter()
+            .map(|(range, new_text)| {
+                let end = range.start.saturating_add_signed(shift) + new_text.len();
+                shift += new_text.len() as isize - range.len() as isize;
+                Selection::cursor(self.document.buffer.position_for_offset(end))
+            })
+            .collect();
+        let newest = self.state.selections.newest_index();
+        self.set_edited_selections(SelectionSet::new(cursors, newest), cx);
+    }
+
+    fn replace_range(&mut self, range: Range<usize>, new_text: &str, cx: &mut ViewContext<Self>) {
+        let changes_lines = new_text.contains('\n')
+            || self.document.buffer.line_for_offset(range.start)
+                != self.document.buffer.line_for_offset(range.end);
+
+        if changes_lines {
+            self.history.finalize_last_transaction();
+        }
+        let selections = self.state.selections.clone();
+        self.edit(range.clone(), new_text, &selections, cx);
+        if changes_lines {
+            self.history.finalize_last_transaction();
+        }
 
-        self.content[index].content = new_content.into();
+        self.state.marked_range.take();
+        let cursor = self
+            .document
+            .buffer
+            .position_for_offset(range.start + new_text.len());
+        self.set_edited_selections(SelectionSet::single(Selection::cursor(cursor)), cx);
     }
 
-    pub fn remove_text_in_range_without_moving(
+    /// Replaces `range` in the buffer, recording the change for undo and
+    /// reporting it to subscribers. `selections_before` are restored when
+    /// the edit starts a transaction that is undone.
+    fn edit(
         &mut self,
-        start: usize,
-        end: usize,
-        index: usize,
-        _cx: &mut ViewContext<Self>,
+        range: Range<usize>,
+        new_text: &str,
+        selections_before: &SelectionSet,
+        cx: &mut ViewContext<Self>,
     ) {
-        self.content[index].content = (self.content[index].content[0..start].to_owned()
-            + &self.content[index].content[end..])
-            .into();
+        let edit = Edit {
+            range: range.clone(),
+            old_text: self.document.buffer.text_for_range(range.clone()),
+            new_text: new_text.to_owned(),
+        };
+        self.replace_in_buffer(range, new_text);
+        cx.emit(TextInputEvent::Edited {
+            range: edit.range.clone(),
+            old_text: edit.old_text.clone(),
+            new_text: edit.new_text.clone(),
+        });
+        self.history
+            .push(edit, selections_before.clone(), Instant::now());
+        cx.notify();
+    }
+
+    /// Replaces text without recording history, keeping the display map
+    /// informed of which lines changed.
+    fn replace_in_buffer(&mut self, range: Range<usize>, new_text: &str) {
+        let start_line = self.document.buffer.line_for_offset(range.start);
+        let end_line = self.document.buffer.line_for_offset(range.end);
+        self.document.buffer.replace(range.clone(), new_text);
+        self.revision += 1;
+        let new_end_line = self
+            .document
+            .buffer
+            .line_for_offset(range.start + new_text.len());
+        self.display_map
+            .edit(start_line..end_line + 1, new_end_line - start_line + 1);
+        self.highlighter
+            .edit(start_line..end_line + 1, new_end_line - start_line + 1);
+        if self.search.is_some() {
+            self.matches.edit(range, new_text.len());
+        }
     }
 
-    pub fn check_bounds(&mut self, index: usize, cx: &mut ViewContext<Self>) {
-        let (Some(bounds), Some(layout)) = (
-            self.last_bounds.as_ref(),
-            self.content[index].last_layout.as_ref(),
-        ) else {
+    /// Highlights again the lines that changed since the display map was
+    /// last used, and rewraps those that are on screen. Other lines are
+    /// wrapped in the background.
+    pub(crate) fn sync_display_map(&mut self, cx: &mut ViewContext<Self>) {
+        if self.markdown {
+            let content = &self.document.buffer;
+            self.highlighter.sync(|line| content.line(line));
+        }
+        let visible = self.visible_lines();
+        let wrap = self.wrap_boundaries(cx);
+        let content = &self.document.buffer;
+        self.display_map
+            .sync(visible, |line| content.line(line), wrap);
+        if self.wrap_task.is_none() && self.display_map.has_stale_lines() {
+            self.wrap_task = Some(cx.spawn(|this, mut cx| async move {
+                loop {
+                    cx.background_executor().timer(WRAP_INTERVAL).await;
+                    let has_more = this.update(&mut cx, |this, cx| {
+                        let anchor = this.scroll_anchor();
+                        let wrap = this.wrap_boundaries(cx);
+                        let content = &this.document.buffer;
+                        let has_more = this.display_map.wrap_stale(
+                            WRAP_CHUNK,
+                            |line| content.line(line),
+                            wrap,
+                        );
+                        if let Some(anchor) = anchor {
+                            this.restore_scroll_anchor(anchor);
+                        }
+                        cx.notify();
+                        has_more
+                    });
+                    if !matches!(has_more, Ok(true)) {
+                        break;
+                    }
+                }
+                this.update(&mut cx, |this, _| this.wrap_task = None).ok();
+            }));
+        }
+    }
+
+    /// Returns the columns at which a line breaks into rows, wrapping it
+    /// the way it was last painted with tabs expanded.
+    fn wrap_boundaries(&self, cx: &WindowContext) -> impl FnMut(&str) -> Vec<usize> {
+        let mut wrapper = self
+            .text_style
+            .as_ref()
+            .zip(self.wrap_width)
+            .map(|(style, width)| {
+                (
+                    cx.text_system().line_wrapper(style.font(), TEXT_SIZE),
+                    width,
+                )
+            });
+        let tab_width = self.tab_width;
+        move |text| match &mut wrapper {
+            Some((wrapper, width)) => {
+                let (expanded, tabs) = TabMap::expand(text, tab_width);
+                wrapper
+                    .wrap_line(&expanded, *width)
+                    .map(|boundary| tabs.to_column(boundary.ix))
+                    .collect()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// The buffer line at the top of the viewport and how far the viewport
+    /// is scrolled past its first row.
+    fn scroll_anchor(&self) -> Option<(usize, Pixels)> {
+        let row_height = self.row_height().filter(|height| *height > px(0.))?;
+        let scroll_top = -self.scroll_handle.0.borrow().base_handle.offset().y;
+        let line = self
+            .display_map
+            .line_for_row((scroll_top / row_height) as usize);
+        let line_top = row_height * self.display_map.rows_for_line(line).start as f32;
+        Some((line, scroll_top - line_top))
+    }
+
+    /// Scrolls so that the line of `scroll_anchor` is back where it was,
+    /// after wrapping changed the number of rows above it.
+    fn restore_scroll_anchor(&self, (line, offset): (usize, Pixels)) {
+        let Some(row_height) = self.row_height() else {
             return;
         };
+        let line_top = row_height * self.display_map.rows_for_line(line).start as f32;
+        let scroll = self.scroll_handle.0.borrow().base_handle.clone();
+        let scroll_offset = scroll.offset();
+        let y = -(line_top + offset).max(px(0.));
+        if y != scroll_offset.y {
+            scroll.set_offset(point(scroll_offset.x, y));
+        }
+    }
+
+    /// Buffer lines of the rows painted last.
+    fn visible_lines(&self) -> Range<usize> {
+        let first = self.last_layouts.keys().min();
+        let last = self.last_layouts.keys().max();
+        match first.zip(last) {
+            Some((first, last)) => {
+                self.display_map.line_for_row(*first)..self.display_map.line_for_row(*last) + 1
+            }
+            None => 0..0,
+        }
+    }
 
-        let pixels =
-            layout.x_for_index(self.content[index].content.len()) + bounds.left() + bounds.right();
-        let width = cx.window_bounds().get_bounds().right()
-            - cx.window_bounds().get_bounds().left()
-            - bounds.right()
-            - bounds.left();
+    /// Called by `TextElement` before painting with the style and width it
+    /// lays rows out at. Changing either rewraps the document on the next
+    /// render.
+    pub(crate) fn set_row_style(
+        &mut self,
+        style: &TextStyle,
+        width: Pixels,
+        cx: &mut ViewContext<Self>,
+    ) {
+        if self.text_style.as_ref() != Some(style) || self.wrap_width != Some(width) {
+            self.text_style = Some(style.clone());
+            self.wrap_width = Some(width);
+            self.display_map.invalidate_all();
+            cx.notify();
+        }
+    }
 
-        if pixels >= width {
-            let content_string = self.content[index].content.to_string();
-            let content_string_len = content_string.len();
-            let mut last_index = layout.closest_index_for_x(width);
+    /// Moves or extends every selection `delta` display rows, keeping each
+    /// head as close as possible to its goal x. Without a goal the current x
+    /// of the head becomes the goal. Heads that would leave the document stay
+    /// where they are.
+    fn move_vertically(&mut self, delta: isize, select: bool, cx: &mut ViewContext<Self>) {
+        self.sync_display_map(cx);
+        let mut goal_xs = Vec::new();
+        let selections = self
+            .state
+            .selections
+            .iter()
+            .enumerate()
+            .map(|(ix, selection)| {
+                let goal_x = self
+                    .state
+                    .goal_xs
+                    .get(ix)
+                    .copied()
+                    .unwrap_or_else(|| self.x_for_position(selection.head, cx));
+                goal_xs.push(goal_x);
+                let head = self
+                    .position_in_row(selection.head, delta, goal_x, cx)
+                    .unwrap_or(selection.head);
+                if select {
+                    Selection::new(selection.anchor, head)
+                } else {
+                    Selection::cursor(head)
+                }
+            })
+            .collect();
+        let newest = self.state.selections.newest_index();
+        self.set_selections(SelectionSet::new(selections, newest), cx);
+        if self.state.selections.len() == goal_xs.len() {
+            self.state.goal_xs = goal_xs;
+        }
+    }
+
+    /// The x of `position` within its display row.
+    fn x_for_position(&self, position: TextPosition, cx: &WindowContext) -> Pixels {
+        let row = self
+            .display_map
+            .row(self.display_map.row_for_position(position));
+        self.shape_row(&row, cx)
+            .x_for_column(position.column - row.columns.start)
+    }
 
-            while last_index > 0 && !content_string[last_index..].starts_with(' ') {
-                last_index -= 1;
+    /// The position `delta` display rows away from `position` that is
+    /// closest to `goal_x`, or `None` past the first or last row.
+    fn position_in_row(
+        &self,
+        position: TextPosition,
+        delta: isize,
+        goal_x: Pixels,
+        cx: &WindowContext,
+    ) -> Option<TextPosition> {
+        let row = self.display_map.row_for_position(position);
+        let target = row.checked_add_signed(delta)?;
+        if target >= self.display_map.row_count() {
+            return None;
+        }
+        let target = self.display_map.row(target);
+        let column = self.shape_row(&target, cx).closest_column_for_x(goal_x);
+        Some(self.clip_to_row(&target, target.columns.start + column))
+    }
+
+    /// Moves or selects a viewport's worth of rows up (`-1`) or down (`1`),
+    /// scrolling the list by the same amount so the cursor keeps its place on
+    /// screen. Stops at the start or end of the document.
+    fn move_by_page(&mut self, direction: isize, select: bool, cx: &mut ViewContext<Self>) {
+        self.sync_display_map(cx);
+        let rows = self.rows_per_page();
+        let row = self.display_map.row_for_position(self.cursor()) as isize;
+        let last_row = self.display_map.row_count() as isize - 1;
+        let delta = (row + direction * rows as isize).clamp(0, last_row) - row;
+
+        let scroll = self.scroll_handle.0.borrow().base_handle.clone();
+        let row_height = self.row_height().unwrap_or_default();
+        let offset = scroll.offset();
+        scroll.set_offset(point(
+            offset.x,
+            (offset.y - row_height * delta as f32).min(px(0.)),
+        ));
+
+        if delta != 0 {
+            self.move_vertically(delta, select, cx);
+        } else {
+            let position = if direction < 0 {
+                TextPosition::default()
+            } else {
+                self.document.buffer.max_position()
+            };
+            if select {
+                self.select_to(position, cx);
+            } else {
+                self.move_to(position, cx);
             }
+        }
+    }
 
-            if last_index == 0 {
-                self.enter(&Enter, cx);
-                return;
+    /// Number of whole rows that fit in the viewport, at least one.
+    fn rows_per_page(&self) -> usize {
+        let viewport = self.scroll_handle.0.borrow().base_handle.bounds();
+        match self.row_height() {
+            Some(row_height) if row_height > px(0.) => {
+                ((viewport.size.height / row_height) as usize).max(1)
             }
+            _ => 1,
+        }
+    }
+
+    /// Height of a row as it was last painted.
+    fn row_height(&self) -> Option<Pixels> {
+        self.last_layouts
+            .values()
+            .next()
+            .map(|painted| painted.bounds.size.height)
+    }
 
-            if last_index > 0 {
-                last_index += 1;
+    /// Lays out `row` the way `TextElement` does, for rows that may not have
+    /// been painted.
+    fn shape_row(&self, row: &DisplayRow, cx: &WindowContext) -> RowLayout {
+        let (text, tabs) = TabMap::expand(
+            &self.document.buffer.line(row.line)[row.columns.clone()],
+            self.tab_width,
+        );
+        let style = self.text_style.clone().unwrap_or_else(|| cx.text_style());
+        let runs = text_runs(&style, text.len(), &self.syntax_highlights(row, &tabs));
+        RowLayout {
+            line: cx
+                .text_system()
+                .shape_line(text.into(), TEXT_SIZE, &runs)
+                .unwrap(),
+            tabs,
+        }
+    }
+
+    /// Markdown highlights of `row`, as ranges of its text with `tabs`
+    /// expanded.
+    pub(crate) fn syntax_highlights(
+        &self,
+        row: &DisplayRow,
+        tabs: &TabMap,
+    ) -> Vec<(Range<usize>, HighlightStyle)> {
+        if !self.markdown {
+            return Vec::new();
+        }
+        self.highlighter
+            .spans(row.line)
+            .iter()
+            .filter_map(|(range, token)| {
+                let start = range.start.max(row.columns.start) - row.columns.start;
+                let end = range
+                    .end
+                    .min(row.columns.end)
+                    .checked_sub(row.columns.start)?;
+                (start < end).then(|| {
+                    (
+                        tabs.to_expanded(start)..tabs.to_expanded(end),
+                        token.style(),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Clips `column` into `row`. The end of a row that wraps is the start of
+    /// the next one, so positions there are pulled back onto this row.
+    fn clip_to_row(&self, row: &DisplayRow, column: usize) -> TextPosition {
+        let mut column = column.clamp(row.columns.start, row.columns.end);
+        if !row.is_line_end && column == row.columns.end {
+            column -= 1;
+        }
+        self.document
+            .buffer
+            .clip_position(TextPosition::new(row.line, column))
+    }
+
+    /// Updates the selections and scrolls the newest cursor into view.
+    /// Moving the selections ends the transaction being typed, so that edits
+    /// made at the new place undo separately.
+    fn set_selections(&mut self, selections: SelectionSet, cx: &mut ViewContext<Self>) {
+        if self.state.selections != selections {
+            self.history.finalize_last_transaction();
+        }
+        self.set_edited_selections(selections, cx)
+    }
+
+    /// Sets the selections an edit leaves behind, which keep its transaction
+    /// open for the edits that follow.
+    fn set_edited_selections(&mut self, selections: SelectionSet, cx: &mut ViewContext<Self>) {
+        self.sync_matches();
+        self.state.goal_xs.clear();
+        if self.state.selections != selections {
+            self.state.selections = selections;
+            self.sync_display_map(cx);
+            self.scroll_handle.scroll_to_item(
+                self.display_map
+                    .row_for_position(self.state.selection().head),
+            );
+            cx.emit(TextInputEvent::SelectionChanged);
+        }
+        cx.notify()
+    }
+
+    /// Replaces all selections with `selection`.
+    fn set_selection(&mut self, selection: Selection, cx: &mut ViewContext<Self>) {
+        self.set_selections(SelectionSet::single(selection), cx)
+    }
+
+    /// Replaces the newest selection, keeping the others.
+    fn set_newest_selection(&mut self, selection: Selection, cx: &mut ViewContext<Self>) {
+        let mut selections = self.state.selections.as_slice().to_vec();
+        let newest = self.state.selections.newest_index();
+        selections[newest] = selection;
+        self.set_selections(SelectionSet::new(selections, newest), cx)
+    }
+
+    /// Collapses every selection to a cursor at the position `f` gives.
+    fn move_selections(
+        &mut self,
+        mut f: impl FnMut(&Self, &Selection) -> TextPosition,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let selections = self
+            .state
+            .selections
+            .map(|selection| Selection::cursor(f(self, selection)));
+        self.set_selections(selections, cx)
+    }
+
+    /// Moves the head of every selection to the position `f` gives, keeping
+    /// the anchors.
+    fn select_heads(
+        &mut self,
+        mut f: impl FnMut(&Self, TextPosition) -> TextPosition,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let selections = self
+            .state
+            .selections
+            .map(|selection| Selection::new(selection.anchor, f(self, selection.head)));
+        self.set_selections(selections, cx)
+    }
+
+    /// Extends every empty selection to the position `f` gives, so that
+    /// deleting removes that text. Does not notify, as the deletion that
+    /// follows does.
+    fn select_empty_to(&mut self, mut f: impl FnMut(&Self, TextPosition) -> TextPosition) {
+        self.state.selections = self.state.selections.map(|selection| {
+            if selection.is_empty() {
+                Selection::new(selection.anchor, f(self, selection.head))
+            } else {
+                *selection
             }
+        });
+    }
+
+    fn move_to(&mut self, position: TextPosition, cx: &mut ViewContext<Self>) {
+        self.set_selection(Selection::cursor(position), cx)
+    }
 
-            let last_index = last_index;
+    /// Extends the newest selection to `position`.
+    fn select_to(&mut self, position: TextPosition, cx: &mut ViewContext<Self>) {
+        let anchor = self.state.selection().anchor;
+        self.set_newest_selection(Selection::new(anchor, position), cx)
+    }
 
-            let len = content_string_len - last_index;
-            let leftovers = &content_string[last_index..content_string_len];
+    /// Head of the newest selection.
+    pub fn cursor(&self) -> TextPosition {
+        self.state.selection().head
+    }
 
-            self.add_word_to_start_of_line(leftovers, index + 1, cx);
-            self.remove_text_in_range_without_moving(last_index - 1, content_string_len, index, cx);
+    fn line_end(&self, position: TextPosition) -> TextPosition {
+        TextPosition::new(position.line, self.document.buffer.line_len(position.line))
+    }
 
-            if self.content[index].selected_range.start >= content_string_len - len
-                && index == self.content_idx
-            {
-                self.content_idx += 1;
-                let pos = len;
-                self.content[self.content_idx].selected_range = pos..pos;
+    /// Selects the block from `anchor_x` on `anchor_row` to the pointer: one
+    /// selection per display row in between, each clipped to the x range of
+    /// the block. While the pointer stays at the click position the block is
+    /// the cursor the alt-click added to the `base` selections; once the drag
+    /// leaves it, `base` is dropped for good and only the block is selected.
+    fn select_block(
+        &mut self,
+        anchor_row: usize,
+        anchor_x: Pixels,
+        base: Vec<Selection>,
+        position: Point<Pixels>,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let Some((head_row, _, head_x)) = self.row_and_x_for_mouse_position(position) else {
+            return;
+        };
+        // Rows may have been rewrapped since the drag started.
+        let last_row = self.display_map.row_count().saturating_sub(1);
+        let (anchor_row, head_row) = (anchor_row.min(last_row), head_row.min(last_row));
+        let mut block = Vec::new();
+        let mut newest = 0;
+        for row_ix in anchor_row.min(head_row)..=anchor_row.max(head_row) {
+            let row = match self.last_layouts.get(&row_ix) {
+                Some(painted) => painted.row.clone(),
+                None => self.display_map.row(row_ix),
+            };
+            let line = self.shape_row(&row, cx);
+            let position_for_x =
+                |x| self.clip_to_row(&row, row.columns.start + line.closest_column_for_x(x));
+            block.push(Selection::new(
+                position_for_x(anchor_x),
+                position_for_x(head_x),
+            ));
+            if row_ix == head_row {
+                newest = block.len() - 1;
             }
-            self.check_bounds(index + 1, cx);
         }
+
+        let at_click = head_row == anchor_row && block[0].is_empty();
+        let selections = if at_click {
+            newest += base.len();
+            base.into_iter().chain(block).collect()
+        } else {
+            if let SelectMode::Column { base, .. } = &mut self.state.select_mode {
+                base.clear();
+            }
+            block
+        };
+        self.set_selections(SelectionSet::new(selections, newest), cx);
+    }
+
+    /// Maps a window position to the text under it, using the bounds and
+    /// layout each row had when it was last painted. Positions above or
+    /// below the visible rows resolve to the first or last of them.
+    pub fn index_for_mouse_position(&self, position: Point<Pixels>) -> TextPosition {
+        let Some((_, painted, x)) = self.row_and_x_for_mouse_position(position) else {
+            return self.cursor();
+        };
+        let column = painted.layout.closest_column_for_x(x);
+        self.clip_to_row(&painted.row, painted.row.columns.start + column)
+    }
+
+    /// The painted display row at a window position, as it was painted, and
+    /// the x of the position relative to the start of that row.
+    fn row_and_x_for_mouse_position(
+        &self,
+        position: Point<Pixels>,
+    ) -> Option<(usize, &PaintedLine, Pixels)> {
+        let hovered = self
+            .last_layouts
+            .iter()
+            .find(|(_, painted)| {
+                painted.bounds.top() <= position.y && position.y < painted.bounds.bottom()
+            })
+            .or_else(|| {
+                let first = self.last_layouts.iter().min_by_key(|(index, _)| **index)?;
+                let last = self.last_layouts.iter().max_by_key(|(index, _)| **index)?;
+                Some(if position.y < first.1.bounds.top() {
+                    first
+                } else {
+                    last
+                })
+            });
+        hovered.map(|(&row, painted)| (row, painted, position.x - painted.bounds.left()))
+    }
+
+    pub fn range_to_utf16(&self, range: &Range<usize>) -> Range<usize> {
+        self.document.buffer.offset_to_utf16(range.start)
+            ..self.document.buffer.offset_to_utf16(range.end)
+    }
+
+    pub fn range_from_utf16(&self, range_utf16: &Range<usize>) -> Range<usize> {
+        self.document.buffer.offset_from_utf16(range_utf16.start)
+            ..self.document.buffer.offset_from_utf16(range_utf16.end)
+    }
+
+    /// The word containing `position`, or the one just before it at the end
+    /// of a line.
+    fn word_at(&self, position: TextPosition) -> Selection {
+        let word = self.document.buffer.word_at(position);
+        Selection::new(word.start, word.end)
+    }
+
+    /// The line containing `position`, including its newline.
+    fn line_at(&self, position: TextPosition) -> Selection {
+        let end = if position.line + 1 < self.document.buffer.line_count() {
+            TextPosition::new(position.line + 1, 0)
+        } else {
+            self.document.buffer.max_position()
+        };
+        Selection::new(TextPosition::new(position.line, 0), end)
+    }
+}
+
+impl EventEmitter<TextInputEvent> for TextInput {}
+
+impl fmt::Display for TextInput {
+    /// Writes the document using the line endings it was loaded with.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.document, f)
     }
 }
 
@@ -438,7 +1612,7 @@ impl ViewInputHandler for TextInput {
         _cx: &mut ViewContext<Self>,
     ) -> Option<String> {
         let range = self.range_from_utf16(&range_utf16);
-        Some(self.content[self.content_idx].content[range].to_string())
+        Some(self.document.buffer.text_for_range(range))
     }
 
     fn selected_text_range(
@@ -447,20 +1621,20 @@ impl ViewInputHandler for TextInput {
         _cx: &mut ViewContext<Self>,
     ) -> Option<UTF16Selection> {
         Some(UTF16Selection {
-            range: self.range_to_utf16(&self.content[self.content_idx].selected_range),
-            reversed: self.content[self.content_idx].selection_reversed,
+            range: self.range_to_utf16(&self.selected_range()),
+            reversed: self.state.selection().reversed(),
         })
     }
 
     fn marked_text_range(&self, _cx: &mut ViewContext<Self>) -> Option<Range<usize>> {
-        self.content[self.content_idx]
+        self.state
             .marked_range
             .as_ref()
             .map(|range| self.range_to_utf16(range))
     }
 
     fn unmark_text(&mut self, _cx: &mut ViewContext<Self>) {
-        self.content[self.content_idx].marked_range = None;
+        self.state.marked_range = None;
     }
 
     fn replace_text_in_range(
@@ -469,23 +1643,17 @@ impl ViewInputHandler for TextInput {
         new_text: &str,
         cx: &mut ViewContext<Self>,
     ) {
+        // Plain typing goes to every selection; IME input only ever targets
+        // the newest one.
         let range = range_utf16
             .as_ref()
             .map(|range_utf16| self.range_from_utf16(range_utf16))
-            .or(self.content[self.content_idx].marked_range.clone())
-            .unwrap_or(self.content[self.content_idx].selected_range.clone());
-
-        self.content[self.content_idx].content =
-            (self.content[self.content_idx].content[0..range.start].to_owned()
-                + new_text
-                + &self.content[self.content_idx].content[range.end..])
-                .into();
-        self.content[self.content_idx].selected_range =
-            range.start + new_text.len()..range.start + new_text.len();
-        self.content[self.content_idx].marked_range.take();
-
-        //self.check_bounds(self.content_idx, cx);
-        cx.notify();
+            .or(self.state.marked_range.clone());
+
+        match range {
+            Some(range) => self.replace_range(range, new_text, cx),
+            None => self.replace_selections(new_text, cx),
+        }
     }
 
     fn replace_and_mark_text_in_range(
@@ -498,40 +1666,58 @@ impl ViewInputHandler for TextInput {
         let range = range_utf16
             .as_ref()
             .map(|range_utf16| self.range_from_utf16(range_utf16))
-            .or(self.content[self.content_idx].marked_range.clone())
-            .unwrap_or(self.content[self.content_idx].selected_range.clone());
-
-        self.content[self.content_idx].content =
-            (self.content[self.content_idx].content[0..range.start].to_owned()
-                + new_text
-                + &self.content[self.content_idx].content[range.end..])
-                .into();
-        self.content[self.content_idx].marked_range =
-            Some(range.start..range.start + new_text.len());
-        self.content[self.content_idx].selected_range = new_selected_range_utf16
+            .or(self.state.marked_range.clone())
+            .unwrap_or(self.selected_range());
+
+        let selections = self.state.selections.clone();
+        self.edit(range.clone(), new_text, &selections, cx);
+        self.state.marked_range = Some(range.start..range.start + new_text.len());
+        let selected_range = new_selected_range_utf16
             .as_ref()
-            .map(|range_utf16| self.range_from_utf16(range_utf16))
-            .map(|new_range| new_range.start + range.start..new_range.end + range.end)
+            .map(|range_utf16| {
+                let start = self.document.buffer.offset_to_utf16(range.start);
+                self.range_from_utf16(&(start + range_utf16.start..start + range_utf16.end))
+            })
             .unwrap_or_else(|| range.start + new_text.len()..range.start + new_text.len());
-
-        cx.notify();
+        let selection = Selection::new(
+            self.document
+                .buffer
+                .position_for_offset(selected_range.start),
+            self.document.buffer.position_for_offset(selected_range.end),
+        );
+        self.set_edited_selections(SelectionSet::single(selection), cx);
     }
 
     fn bounds_for_range(
         &mut self,
         range_utf16: Range<usize>,
-        bounds: Bounds<Pixels>,
+        _bounds: Bounds<Pixels>,
         _cx: &mut ViewContext<Self>,
     ) -> Option<Bounds<Pixels>> {
-        let last_layout = self.content[self.content_idx].last_layout.as_ref()?;
         let range = self.range_from_utf16(&range_utf16);
+        let start = self.document.buffer.position_for_offset(range.start);
+        let end = self.document.buffer.position_for_offset(range.end);
+        let PaintedLine {
+            bounds,
+            row,
+            layout: last_layout,
+        } = self
+            .last_layouts
+            .values()
+            .find(|painted| painted.row.contains(start))?;
+        let start_column = start.column - row.columns.start;
+        let end_column = if end.line == start.line {
+            end.column.min(row.columns.end) - row.columns.start
+        } else {
+            row.columns.len()
+        };
         Some(Bounds::from_corners(
             point(
-                bounds.left() + last_layout.x_for_index(range.start),
+                bounds.left() + last_layout.x_for_column(start_column),
                 bounds.top(),
             ),
             point(
-                bounds.left() + last_layout.x_for_index(range.end),
+                bounds.left() + last_layout.x_for_column(end_column),
                 bounds.bottom(),
             ),
         ))
@@ -540,6 +1726,11 @@ impl ViewInputHandler for TextInput {
 
 impl Render for TextInput {
     fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        self.sync_display_map(cx);
+        self.sync_matches();
+        // Only visible rows are painted, and they repopulate this below.
+        self.last_layouts.clear();
+
         div()
             .p(px(4.))
             .flex()
@@ -552,23 +1743,65 @@ impl Render for TextInput {
             .on_action(cx.listener(Self::right))
             .on_action(cx.listener(Self::select_left))
             .on_action(cx.listener(Self::select_right))
+            .on_action(cx.listener(Self::move_word_left))
+            .on_action(cx.listener(Self::move_word_right))
+            .on_action(cx.listener(Self::select_word_left))
+            .on_action(cx.listener(Self::select_word_right))
+            .on_action(cx.listener(Self::delete_word_backward))
+            .on_action(cx.listener(Self::delete_word_forward))
             .on_action(cx.listener(Self::select_all))
+            .on_action(cx.listener(Self::select_next_occurrence))
+            .on_action(cx.listener(Self::cancel))
             .on_action(cx.listener(Self::home))
             .on_action(cx.listener(Self::end))
             .on_action(cx.listener(Self::show_character_palette))
             .on_action(cx.listener(Self::enter))
+            .on_action(cx.listener(Self::tab))
+            .on_action(cx.listener(Self::indent))
+            .on_action(cx.listener(Self::outdent))
             .on_action(cx.listener(Self::up))
             .on_action(cx.listener(Self::down))
+            .on_action(cx.listener(Self::page_up))
+            .on_action(cx.listener(Self::page_down))
+            .on_action(cx.listener(Self::select_page_up))
+            .on_action(cx.listener(Self::select_page_down))
+            .on_action(cx.listener(Self::select_up))
+            .on_action(cx.listener(Self::select_down))
+            .on_action(cx.listener(Self::select_to_line_start))
+            .on_action(cx.listener(Self::select_to_line_end))
+            .on_action(cx.listener(Self::move_to_document_start))
+            .on_action(cx.listener(Self::move_to_document_end))
+            .on_action(cx.listener(Self::select_to_document_start))
+            .on_action(cx.listener(Self::select_to_document_end))
+            .on_action(cx.listener(Self::undo))
+            .on_action(cx.listener(Self::redo))
+            .on_action(cx.listener(Self::copy))
+            .on_action(cx.listener(Self::cut))
+            .on_action(cx.listener(Self::paste))
+            .on_action(cx.listener(Self::find_next))
+            .on_action(cx.listener(Self::find_previous))
             .on_mouse_down(MouseButton::Left, cx.listener(Self::on_mouse_down))
             .on_mouse_up(MouseButton::Left, cx.listener(Self::on_mouse_up))
             .on_mouse_up_out(MouseButton::Left, cx.listener(Self::on_mouse_up))
             .on_mouse_move(cx.listener(Self::on_mouse_move))
-            .text_size(px(12.))
-            .children((0..self.content.len()).map(|i| {
-                div().pt(px(14. * i as f32)).child(TextElement {
-                    input: cx.view().clone(),
-                    index: i,
-                })
-            }))
+            .text_size(TEXT_SIZE)
+            .size_full()
+            .child(
+                uniform_list(
+                    cx.view().clone(),
+                    "text-input-rows",
+                    self.display_map.row_count(),
+                    |_, range, cx| {
+                        range
+                            .map(|index| TextElement {
+                                input: cx.view().clone(),
+                                index,
+                            })
+                            .collect()
+                    },
+                )
+                .track_scroll(self.scroll_handle.clone())
+                .size_full(),
+            )
     }
 }